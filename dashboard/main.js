@@ -8,15 +8,39 @@ ctx.imageSmoothingEnabled = false
 const tileColours = [
     // Nothing
     "white",
-    // Block
+    // Solid
     "black",
     // Enemy
     "orange",
     // Mario
-    "red"
+    "red",
+    // Hazard
+    "purple",
+    // Projectile
+    "grey",
+    // Boss
+    "darkred",
+    // Breakable
+    "saddlebrown",
+    // Question
+    "gold",
+    // Pipe
+    "green",
+    // Coin
+    "yellow",
+    // Power-up
+    "deeppink",
+    // Pit
+    "lightblue"
 ]
 
 const renderScreen = (screen) => {
+	// The size of the view is configurable
+	if (canvas.height !== screen.length || canvas.width !== screen[0].length) {
+		canvas.height = screen.length
+		canvas.width = screen[0].length
+		ctx.imageSmoothingEnabled = false
+	}
 	screen.forEach((row, rowIndex) => {
 		row.forEach((tile, tileIndex) => {
 			ctx.fillStyle = tileColours[tile]
@@ -25,6 +49,16 @@ const renderScreen = (screen) => {
 	})
 }
 
+const renderLevels = (fitnesses) => {
+	const list = document.querySelector("#levels")
+	list.innerHTML = ""
+	fitnesses.forEach((fitness) => {
+		const item = document.createElement("li")
+		item.textContent = fitness
+		list.appendChild(item)
+	})
+}
+
 let renderScreenTimeout = null
 
 ws.addEventListener("message", (e) => {
@@ -35,5 +69,8 @@ ws.addEventListener("message", (e) => {
             cancelAnimationFrame(renderScreenTimeout)
         }
         renderScreenTimeout = requestAnimationFrame(() => renderScreen(data))
+        break
+	case "update_levels":
+		renderLevels(data)
     }
 })
@@ -0,0 +1,58 @@
+use super::fitness::Outcome;
+use super::game_state::{self, GameState};
+use super::ScalarInput;
+use crate::nes::{cpu, mem};
+use crate::utils::{Screen, View};
+
+use serde::{Deserialize, Serialize};
+
+// Everything the AI needs to know about the game being played. Implementing
+// this for another platformer that runs on the emulator lets the same NEAT
+// loop train on it. Adapters fill in the fields of `GameState` that make sense
+// for their game, and the rest of the AI only reads them through the adapter
+pub trait GameAdapter {
+    // Reads the game's state from RAM, once per frame
+    fn read_state(&self, cpu: &mut cpu::Cpu<mem::MemMap>) -> GameState;
+
+    // The tiles of the view around the player, shown on the dashboard and
+    // fed to the network in the tile observation mode
+    fn read_screen(&self, cpu: &mut cpu::Cpu<mem::MemMap>, state: &GameState, view: View)
+        -> Screen;
+
+    // How the run ended, if it ended between the previous frame and this one.
+    // Runs that don't end by themselves are stopped by the stuck and finish
+    // timeouts
+    fn outcome(&self, previous: &GameState, state: &GameState) -> Option<Outcome>;
+
+    // Where the top-left corner of the player is drawn on the frame, in
+    // pixels, to centre the pixel observation on them
+    fn player_screen_position(&self, state: &GameState) -> (i32, i32);
+
+    // Value of a scalar input other than the bias, scaled to roughly [-1, 1],
+    // on the level of the save state at index `level`. Inputs that don't apply
+    // to the game are 0
+    fn scalar_input(&self, input: &ScalarInput, state: &GameState, level: usize) -> f64;
+
+    // How far through the level the player is, in pixels. The stuck timeout
+    // waits for it to change, and the distance based fitness functions
+    // reward it
+    fn progress(&self, state: &GameState) -> u64;
+
+    // Points scored in the game, for the score fitness function
+    fn score(&self, state: &GameState) -> u64;
+}
+
+// The games that can be selected from the config
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum Game {
+    #[default]
+    SuperMarioBros,
+}
+
+impl Game {
+    pub fn adapter(self) -> &'static dyn GameAdapter {
+        match self {
+            Game::SuperMarioBros => &game_state::SuperMarioBros,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use super::snapshot::{self, SnapshotError};
+use super::{
+    AiOptions, FitnessFunction, Individual, IndividualState, IndividualStateOptions, Inputs,
+    ScalarInput,
+};
+use crate::nes::input::Button;
+use crate::nes::{cpu, mem};
+use crate::utils::Screen;
+
+use std::path::Path;
+use std::sync::Arc;
+
+// A single, fixed network playing the game from the save state, without any
+// of the evolution machinery. As neither the network nor the emulator use any
+// randomness, a controller always plays the same way
+pub struct Controller {
+    individual: Individual,
+    inputs: Vec<ScalarInput>,
+    outputs: Vec<Button>,
+    state: IndividualState,
+    fitness_function: Arc<dyn FitnessFunction + Send + Sync>,
+}
+
+impl Controller {
+    pub(super) fn new(
+        individual: Individual,
+        inputs: Vec<ScalarInput>,
+        outputs: Vec<Button>,
+        options: IndividualStateOptions,
+        fitness_function: Arc<dyn FitnessFunction + Send + Sync>,
+    ) -> Self {
+        Self {
+            individual,
+            inputs,
+            outputs,
+            state: IndividualState::new(options, 0),
+            fitness_function,
+        }
+    }
+
+    // Loads a genome file. The game and the network's structure (its inputs,
+    // outputs, view, observation and whether it is recurrent) come from the
+    // file, while the timeouts and fitness function come from `options`
+    pub fn load(path: &str, options: &AiOptions) -> Result<Self, SnapshotError> {
+        let genome = snapshot::read_genome(Path::new(path))?;
+        println!(
+            "Loaded genome from generation {} (fitness = {})",
+            genome.generation, genome.fitness
+        );
+        let state_options = IndividualStateOptions {
+            recurrent: genome.recurrent,
+            view: genome.view,
+            observation: genome.observation,
+            game: genome.game,
+            ..IndividualStateOptions::from(options)
+        };
+        Ok(Self::new(
+            genome.individual,
+            genome.inputs,
+            genome.outputs,
+            state_options,
+            Arc::new(options.fitness),
+        ))
+    }
+
+    // Starts playing again from scratch on the level of the given save state
+    pub fn restart(&mut self, level: usize) {
+        self.state = IndividualState::new(self.state.options, level);
+    }
+
+    pub fn update_game_state(&mut self, cpu: &mut cpu::Cpu<mem::MemMap>) {
+        self.state.update(cpu);
+    }
+
+    pub fn get_inputs(&mut self) -> Inputs {
+        self.individual
+            .get_inputs(&mut self.state, &self.inputs, &self.outputs)
+    }
+
+    pub fn is_stuck(&self) -> bool {
+        self.state.is_stuck()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.state.is_dead()
+    }
+
+    pub fn has_succeeded(&self) -> bool {
+        self.state.has_succeeded()
+    }
+
+    pub fn get_screen(&self) -> Screen {
+        self.state.get_screen()
+    }
+
+    pub fn fitness(&self) -> u64 {
+        self.state.fitness(&*self.fitness_function)
+    }
+}
@@ -0,0 +1,80 @@
+use super::adapter::GameAdapter;
+use super::game_state::GameState;
+
+use serde::{Deserialize, Serialize};
+
+// Bonus for reaching the end of a level, larger than the length of any level
+const SUCCESS_BONUS: u64 = 1000;
+
+// How a run ended
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Outcome {
+    Stuck,
+    Dead,
+    Succeeded,
+}
+
+// Scores a run from the game state on each of its frames, in order, and from
+// how it ended. The adapter of the game being played tells how far the player
+// got and what they scored. Implement this to try other reward shaping, and
+// pass it to `Ai::set_fitness_function`
+pub trait FitnessFunction {
+    fn fitness(&self, adapter: &dyn GameAdapter, states: &[GameState], outcome: Outcome) -> u64;
+}
+
+// The fitness functions that can be selected from the config
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BuiltinFitness {
+    // How far the player got, plus a bonus for reaching the end of the level
+    Distance,
+    // Distance, plus one point per frame under `par_frames` for runs that
+    // reach the end of the level
+    DistanceWithTimeBonus { par_frames: u64 },
+    // Any run that reaches the end of the level beats every run that doesn't,
+    // and faster runs beat slower ones
+    Speedrun,
+    // Points scored during the run, plus the distance so that runs which
+    // score nothing are still ranked
+    Score,
+}
+
+impl FitnessFunction for BuiltinFitness {
+    fn fitness(&self, adapter: &dyn GameAdapter, states: &[GameState], outcome: Outcome) -> u64 {
+        let distance =
+            |states: &[GameState]| states.last().map_or(0, |state| adapter.progress(state));
+        let succeeded = outcome == Outcome::Succeeded;
+        let frames = states.len() as u64;
+        match *self {
+            BuiltinFitness::Distance => {
+                distance(states) + if succeeded { SUCCESS_BONUS } else { 0 }
+            }
+            BuiltinFitness::DistanceWithTimeBonus { par_frames } => {
+                let time_bonus = if succeeded {
+                    SUCCESS_BONUS + par_frames.saturating_sub(frames)
+                } else {
+                    0
+                };
+                distance(states) + time_bonus
+            }
+            BuiltinFitness::Speedrun => {
+                if succeeded {
+                    // More than any distance, and runs are cut short long
+                    // before they reach this many frames
+                    const MAX_FRAMES: u64 = 1_000_000;
+                    SUCCESS_BONUS + MAX_FRAMES.saturating_sub(frames)
+                } else {
+                    distance(states)
+                }
+            }
+            BuiltinFitness::Score => {
+                let score = match (states.first(), states.last()) {
+                    (Some(first), Some(last)) => {
+                        adapter.score(last).saturating_sub(adapter.score(first))
+                    }
+                    _ => 0,
+                };
+                score + distance(states)
+            }
+        }
+    }
+}
@@ -1,28 +1,78 @@
+use super::adapter::GameAdapter;
+use super::fitness::Outcome;
+use super::ScalarInput;
 use crate::nes::{cpu, mem};
-use crate::utils::{Screen, Tile, VIEW_SIZE};
+use crate::utils::{Screen, Tile, View};
 
 use std::fmt;
 
+#[derive(Eq, PartialEq, Default, Clone, Copy, Debug)]
+pub enum PowerUp {
+    #[default]
+    Small,
+    Big,
+    Fiery,
+}
+
 #[derive(Eq, PartialEq, Default, Clone, Copy)]
 pub struct GameState {
     pub mario_x: u16,
     pub mario_y: u16,
+    // Signed speeds, in the units the game uses internally
+    pub horizontal_speed: i8,
+    pub vertical_speed: i8,
+    pub on_ground: bool,
+    // Whether Mario has fallen off the bottom of the screen, into a pit
+    pub below_screen: bool,
+    pub power_up: PowerUp,
     pub screen_x: u8,
     pub lives: u8,
+    // Both are 0-based. The level counts the areas of the world, some of
+    // which (such as the pipe intro of World 1-2) aren't numbered by the game
+    pub world: u8,
     pub level: u8,
+    pub score: u32,
+    pub coins: u8,
+    // In-game time left, in game time units
+    pub timer: u16,
+    // Raw state bytes: what the game engine is doing with Mario (e.g. normal
+    // play, entering a pipe or dying), whether he is jumping or falling, and
+    // whether the game is being played, between worlds or over
+    pub player_state: u8,
+    pub float_state: u8,
+    pub game_mode: u8,
 }
 
 impl fmt::Debug for GameState {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         formatter.write_fmt(format_args!(
-            "Mario coords: ({}, {}). Lives: {}. Screen X: {}. Level: {}",
-            self.mario_x, self.mario_y, self.lives, self.screen_x, self.level
+            "Mario coords: ({}, {}). Speed: ({}, {}). On ground: {}. Below screen: {}. Power-up: {:?}. ",
+            self.mario_x,
+            self.mario_y,
+            self.horizontal_speed,
+            self.vertical_speed,
+            self.on_ground,
+            self.below_screen,
+            self.power_up
+        ))?;
+        formatter.write_fmt(format_args!(
+            "Lives: {}. Screen X: {}. World: {}. Level: {}. Score: {}. Coins: {}. Timer: {}. ",
+            self.lives, self.screen_x, self.world, self.level, self.score, self.coins, self.timer
+        ))?;
+        formatter.write_fmt(format_args!(
+            "Player state: {}. Float state: {}. Game mode: {}",
+            self.player_state, self.float_state, self.game_mode
         ))
     }
 }
 
+// Reads a number stored as one decimal digit per byte, most significant first
+fn read_digits(cpu: &mut cpu::Cpu<mem::MemMap>, first_addr: u16, digits: u16) -> u32 {
+    (first_addr..first_addr + digits).fold(0, |number, addr| number * 10 + cpu.loadb(addr) as u32)
+}
+
 // Source for memory addresses: https://datacrystal.romhacking.net/wiki/Super_Mario_Bros.:RAM_map
-pub fn get_state(cpu: &mut cpu::Cpu<mem::MemMap>) -> GameState {
+fn get_state(cpu: &mut cpu::Cpu<mem::MemMap>) -> GameState {
     let mario_x = {
         let mario_level_x = cpu.loadb(0x6D) as u16;
         let mario_screen_x = cpu.loadb(0x86) as u16;
@@ -32,68 +82,335 @@ pub fn get_state(cpu: &mut cpu::Cpu<mem::MemMap>) -> GameState {
         let mario_screen_y = cpu.loadb(0x3B8) as u16;
         mario_screen_y + 16
     };
+    let horizontal_speed = cpu.loadb(0x57) as i8;
+    let vertical_speed = cpu.loadb(0x9F) as i8;
+    // The float state is 0 when standing on the ground, and non-zero when
+    // jumping, falling or sliding down the flagpole
+    let float_state = cpu.loadb(0x1D);
+    let on_ground = float_state == 0;
+    // Mario's vertical screen position is 1 while he is on the screen, and
+    // goes above 1 once he has fallen below it
+    let below_screen = cpu.loadb(0xB5) > 1;
+    let power_up = match cpu.loadb(0x756) {
+        0 => PowerUp::Small,
+        1 => PowerUp::Big,
+        _ => PowerUp::Fiery,
+    };
     let screen_x = cpu.loadb(0x3AD);
     let lives = cpu.loadb(0x75A);
+    let world = cpu.loadb(0x75F);
     let level = cpu.loadb(0x760);
+    // The score's digits go from millions down to tens
+    let score = read_digits(cpu, 0x7DD, 6) * 10;
+    let coins = cpu.loadb(0x75E);
+    let timer = read_digits(cpu, 0x7F8, 3) as u16;
+    let player_state = cpu.loadb(0xE);
+    let game_mode = cpu.loadb(0x770);
     GameState {
         mario_x,
         mario_y,
+        horizontal_speed,
+        vertical_speed,
+        on_ground,
+        below_screen,
+        power_up,
         screen_x,
         lives,
+        world,
         level,
+        score,
+        coins,
+        timer,
+        player_state,
+        float_state,
+        game_mode,
+    }
+}
+
+// Area covered by an enemy or projectile, in level coordinates
+struct Hitbox {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+    tile: Tile,
+}
+
+// Category of an enemy slot's object, from its type. Objects that don't get
+// in Mario's way (power-ups, flags, vines, ...) are left out
+fn enemy_tile(enemy_type: u8) -> Option<Tile> {
+    match enemy_type {
+        // Koopas, Buzzy Beetles, Hammer Bros, Goombas, Paratroopas, Lakitus
+        // and flying Cheep Cheeps, which can all be stomped
+        0x00..=0x06 | 0x09 | 0x0E..=0x11 | 0x14 => Some(Tile::Enemy),
+        // Bloopers and swimming Cheep Cheeps, Podoboos, Piranha Plants,
+        // Spinies and Fire Bars, which can't
+        0x07 | 0x0A..=0x0D | 0x12 | 0x1B..=0x1F => Some(Tile::Hazard),
+        // Bullet Bills and Bowser's flames
+        0x08 | 0x15 | 0x33 => Some(Tile::Projectile),
+        // Lifts and springboards can be stood on
+        0x24..=0x2C | 0x32 => Some(Tile::Solid),
+        0x2D => Some(Tile::Boss),
+        0x2E => Some(Tile::PowerUp),
+        _ => None,
+    }
+}
+
+// Width and height of an enemy slot's object, in pixels
+fn enemy_size(enemy_type: u8) -> (u16, u16) {
+    match enemy_type {
+        // Green and red Koopas (0x00, 0x01, 0x03 and 0x04), Hammer Bros,
+        // Bloopers, Piranha Plants and Paratroopas. Buzzy Beetles (0x02) are
+        // as short as Goombas
+        0x00 | 0x01 | 0x03..=0x05 | 0x07 | 0x0D..=0x10 => (16, 24),
+        // Only the centre of a Fire Bar, whose arm rotates around it
+        0x1B..=0x1F => (8, 8),
+        0x15 => (24, 8),
+        // Lifts
+        0x24..=0x2C => (32, 8),
+        0x2D => (32, 32),
+        _ => (16, 16),
+    }
+}
+
+// Source for memory addresses: https://datacrystal.romhacking.net/wiki/Super_Mario_Bros.:RAM_map
+// and the SMB disassembly, which stores the positions of every object in the
+// same arrays as Mario's: enemies from offset 1 and misc objects from 13
+fn get_enemies(cpu: &mut cpu::Cpu<mem::MemMap>) -> Vec<Hitbox> {
+    fn position(cpu: &mut cpu::Cpu<mem::MemMap>, offset: u16) -> (u16, u16) {
+        let x = (cpu.loadb(0x6D + offset) as u16 * 0x100) + cpu.loadb(0x86 + offset) as u16;
+        let y = cpu.loadb(0xCE + offset) as u16 + 24;
+        (x, y)
     }
+
+    let mut enemies = vec![];
+    for slot in 0..=4 {
+        if cpu.loadb(0xF + slot) == 0 {
+            continue;
+        }
+        let enemy_type = cpu.loadb(0x16 + slot);
+        if let Some(tile) = enemy_tile(enemy_type) {
+            let (x, y) = position(cpu, 1 + slot);
+            let (width, height) = enemy_size(enemy_type);
+            enemies.push(Hitbox {
+                x,
+                y,
+                width,
+                height,
+                tile,
+            });
+        }
+    }
+    // Misc objects are Hammer Bros' hammers and coins popping out of blocks.
+    // Only hammers set bit 7 of their state
+    for slot in 0..9 {
+        if cpu.loadb(0x2A + slot) & 0x80 == 0 {
+            continue;
+        }
+        let (x, y) = position(cpu, 13 + slot);
+        enemies.push(Hitbox {
+            x,
+            y,
+            width: 8,
+            height: 8,
+            tile: Tile::Projectile,
+        });
+    }
+    enemies
 }
 
-pub fn get_screen(cpu: &mut cpu::Cpu<mem::MemMap>, game_state: GameState) -> Screen {
+// Class of a metatile of the level, from its ID. Metatiles that aren't
+// listed are scenery Mario goes through (bushes, hills, castles, clouds,
+// ropes, the flagpole, vines, water, ...)
+fn metatile_tile(metatile: u8) -> Tile {
+    match metatile {
+        // Vertical and sideways pipes
+        0x10..=0x15 | 0x1C..=0x21 => Tile::Pipe,
+        // Tree and mushroom ledges, coral, and the blank left behind while a
+        // block bounces after being hit
+        0x16..=0x1B | 0x22 | 0x23 => Tile::Solid,
+        // Bricks, including the ones holding coins, a power-up, a vine, a
+        // star or a 1-up
+        0x51..=0x53 | 0x55..=0x5E => Tile::Breakable,
+        // Ground
+        0x54 => Tile::Solid,
+        // Hidden coin and 1-up blocks, which can only be hit from below
+        0x5F | 0x60 => Tile::Nothing,
+        // Stair blocks, walls, bridges, Bullet Bill cannons, springboard
+        // bases, underwater rocks and pipes
+        0x61..=0x66 | 0x68..=0x6C => Tile::Solid,
+        // Cloud level terrain and Bowser's bridge
+        0x88 | 0x89 => Tile::Solid,
+        0xC0 => Tile::Question,
+        0xC1 => Tile::PowerUp,
+        // Coins above ground and underwater
+        0xC2 | 0xC3 => Tile::Coin,
+        // Blocks that have already been hit
+        0xC4 => Tile::Solid,
+        _ => Tile::Nothing,
+    }
+}
+
+fn get_screen(cpu: &mut cpu::Cpu<mem::MemMap>, game_state: GameState, view: View) -> Screen {
+    // The level is 13 metatiles high
+    const LEVEL_HEIGHT: i32 = 13;
+
+    fn get_metatile(sub_x: i32, sub_y: i32, page: i32, cpu: &mut cpu::Cpu<mem::MemMap>) -> u8 {
+        let addr = 0x500 + (page * LEVEL_HEIGHT * 16) + (sub_y * 16) + sub_x;
+        cpu.loadb(addr as u16)
+    }
+
     fn get_tile(x: i32, y: i32, cpu: &mut cpu::Cpu<mem::MemMap>) -> Tile {
         let sub_y = (y - 32) / 16;
-        if sub_y >= 13 || sub_y < 0 {
-            return Tile::Nothing;
-        }
         let sub_x = (x % 256) / 16;
         let page = (x / 256) % 2;
-        let addr = 0x500 + (page * 13 * 16) + (sub_y * 16) + sub_x;
-        if cpu.loadb(addr as u16) == 0 {
-            Tile::Nothing
-        } else {
-            Tile::Block
+        if sub_y < 0 {
+            return Tile::Nothing;
         }
-    }
-
-    fn get_enemies(cpu: &mut cpu::Cpu<mem::MemMap>) -> Vec<(u16, u16)> {
-        let mut enemies = vec![];
-        for slot in 0..=4 {
-            let enemy = cpu.loadb(0xF + slot);
-            if enemy != 0 {
-                let e_x = (cpu.loadb(0x6E + slot) as u16 * 0x100) + cpu.loadb(0x87 + slot) as u16;
-                let e_y = cpu.loadb(0xCF + slot) as u16 + 24;
-                enemies.push((e_x, e_y));
-            }
+        // Columns with nothing on the bottom row are pits, which show from
+        // that row down
+        let bottom = LEVEL_HEIGHT - 1;
+        if sub_y >= bottom && get_metatile(sub_x, bottom, page, cpu) == 0 {
+            return Tile::Pit;
         }
-        enemies
+        if sub_y > bottom {
+            return Tile::Nothing;
+        }
+        metatile_tile(get_metatile(sub_x, sub_y, page, cpu))
     }
 
     const BLOCK_SIZE: i32 = 16;
-    let mut screen = Screen::default();
-    for i in -VIEW_SIZE..=VIEW_SIZE {
+    let (above, behind) = (view.above as i32, view.behind as i32);
+    let mut screen = view.empty_screen();
+    for i in -above..=view.below as i32 {
         let dy = i * BLOCK_SIZE;
         let y = game_state.mario_y as i32 + dy - BLOCK_SIZE;
 
-        for j in -VIEW_SIZE..=VIEW_SIZE {
+        for j in -behind..=view.ahead as i32 {
             let dx = j * BLOCK_SIZE;
             let x = game_state.mario_x as i32 + dx + 8;
-            screen[(i + VIEW_SIZE) as usize][(j + VIEW_SIZE) as usize] = get_tile(x, y, cpu)
+            screen[(i + above) as usize][(j + behind) as usize] = get_tile(x, y, cpu)
         }
     }
-    screen[VIEW_SIZE as usize + 1][VIEW_SIZE as usize] = Tile::Mario;
+    // Mario is on the row below the centre of the view, which is off the
+    // view if it doesn't extend below him
+    if let Some(row) = screen.get_mut(view.above + 1) {
+        row[view.behind] = Tile::Mario;
+    }
 
-    for (e_x, e_y) in get_enemies(cpu) {
-        let i = (e_y as i32 - game_state.mario_y as i32) / BLOCK_SIZE + VIEW_SIZE;
-        let j = (e_x as i32 - game_state.mario_x as i32) / BLOCK_SIZE + VIEW_SIZE;
-        if (0 <= i && i < 13) && (0 <= j && j < 13) {
-            screen[i as usize][j as usize] = Tile::Enemy;
+    // Each hitbox is marked on every cell it overlaps
+    let cell = |position: u16, mario_position: u16| {
+        (position as i32 - mario_position as i32).div_euclid(BLOCK_SIZE)
+    };
+    let (last_row, last_column) = (view.height() as i32 - 1, view.width() as i32 - 1);
+    for hitbox in get_enemies(cpu) {
+        let top = (cell(hitbox.y, game_state.mario_y) + above).max(0);
+        let bottom = (cell(hitbox.y + hitbox.height - 1, game_state.mario_y) + above).min(last_row);
+        let left = (cell(hitbox.x, game_state.mario_x) + behind).max(0);
+        let right =
+            (cell(hitbox.x + hitbox.width - 1, game_state.mario_x) + behind).min(last_column);
+        for i in top..=bottom {
+            for j in left..=right {
+                screen[i as usize][j as usize] = hitbox.tile;
+            }
         }
     }
 
     screen
 }
+
+// Super Mario Bros, read through the RAM map above
+pub struct SuperMarioBros;
+
+impl GameAdapter for SuperMarioBros {
+    fn read_state(&self, cpu: &mut cpu::Cpu<mem::MemMap>) -> GameState {
+        get_state(cpu)
+    }
+
+    fn read_screen(
+        &self,
+        cpu: &mut cpu::Cpu<mem::MemMap>,
+        state: &GameState,
+        view: View,
+    ) -> Screen {
+        get_screen(cpu, *state, view)
+    }
+
+    // Mario dies as soon as he is hit or falls into a pit, rather than when he
+    // loses a life at the end of the death animation. He succeeds as soon as
+    // he grabs the flagpole, as he stands still during the slide, the walk to
+    // the castle and the time tally, long enough to be considered stuck.
+    // Levels that end without a flagpole succeed when the next level starts,
+    // levels being ordered by world first
+    fn outcome(&self, previous: &GameState, state: &GameState) -> Option<Outcome> {
+        // Player states of the death animation, after a hit and after falling
+        const DYING: u8 = 0x0B;
+        const DEAD: u8 = 0x06;
+        // Player states of the flagpole slide and the end of level sequence.
+        // The climbing float state isn't used, as Mario also climbs vines
+        const FLAGPOLE_SLIDE: u8 = 0x04;
+        const END_OF_LEVEL: u8 = 0x05;
+
+        let is_dying = state.player_state == DYING || state.player_state == DEAD;
+        let reached_flagpole =
+            state.player_state == FLAGPOLE_SLIDE || state.player_state == END_OF_LEVEL;
+        if is_dying || state.below_screen || state.lives < previous.lives {
+            Some(Outcome::Dead)
+        } else if reached_flagpole || (state.world, state.level) > (previous.world, previous.level)
+        {
+            Some(Outcome::Succeeded)
+        } else {
+            None
+        }
+    }
+
+    // Mario's y coordinate is 16 below his position on the screen
+    fn player_screen_position(&self, state: &GameState) -> (i32, i32) {
+        (state.screen_x as i32, state.mario_y as i32 - 16)
+    }
+
+    fn scalar_input(&self, input: &ScalarInput, state: &GameState, level: usize) -> f64 {
+        // Mario's top running speed
+        const MAX_HORIZONTAL_SPEED: f64 = 40.0;
+        const MAX_VERTICAL_SPEED: f64 = 5.0;
+        // Time given for most levels
+        const MAX_TIMER: f64 = 400.0;
+
+        match input {
+            ScalarInput::HorizontalSpeed => {
+                f64::from(state.horizontal_speed) / MAX_HORIZONTAL_SPEED
+            }
+            ScalarInput::VerticalSpeed => f64::from(state.vertical_speed) / MAX_VERTICAL_SPEED,
+            ScalarInput::OnGround => {
+                if state.on_ground {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ScalarInput::DistanceToLevelEnd { level_end_x } => {
+                // Either one value per save state, or one for all of them
+                let level_end_x = level_end_x.get(level).or(level_end_x.last());
+                let level_end_x = level_end_x.cloned().unwrap_or(0);
+                let remaining = level_end_x.saturating_sub(state.mario_x);
+                f64::from(remaining) / f64::from(level_end_x.max(1))
+            }
+            ScalarInput::PowerUp => match state.power_up {
+                PowerUp::Small => 0.0,
+                PowerUp::Big => 0.5,
+                PowerUp::Fiery => 1.0,
+            },
+            ScalarInput::Timer => f64::from(state.timer) / MAX_TIMER,
+            // `ScalarInput::value` never asks the adapter for the bias
+            ScalarInput::Bias => unreachable!(),
+        }
+    }
+
+    fn progress(&self, state: &GameState) -> u64 {
+        state.mario_x as u64
+    }
+
+    fn score(&self, state: &GameState) -> u64 {
+        state.score as u64
+    }
+}
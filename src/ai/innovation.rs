@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+// Innovation numbers and node ids handed out when a connection is split by a
+// new node
+#[derive(Copy, Clone)]
+pub struct SplitConnection {
+    pub node: usize,
+    // Innovation number of the connection into the new node
+    pub in_innovation_number: u64,
+    // Innovation number of the connection out of the new node
+    pub out_innovation_number: u64,
+}
+
+// Hands out innovation numbers and node ids for structural mutations. As in
+// the NEAT paper, identical mutations made during the same generation are
+// given the same innovation numbers (and node ids), so that the genes they
+// create line up during crossover and when measuring compatibility distance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InnovationRegistry {
+    next_innovation_number: u64,
+    next_node_id: usize,
+    // Mutations seen during the current generation, which do not need to
+    // outlive it
+    #[serde(skip)]
+    connections: HashMap<(usize, usize), u64>,
+    #[serde(skip)]
+    split_connections: HashMap<u64, SplitConnection>,
+}
+
+impl InnovationRegistry {
+    pub fn new(next_node_id: usize) -> Self {
+        Self {
+            next_innovation_number: 0,
+            next_node_id,
+            connections: HashMap::new(),
+            split_connections: HashMap::new(),
+        }
+    }
+
+    // Carries on from the innovation numbers and node ids handed out by a run
+    // whose registry wasn't saved
+    pub fn resume(next_innovation_number: u64, next_node_id: usize) -> Self {
+        Self {
+            next_innovation_number,
+            ..Self::new(next_node_id)
+        }
+    }
+
+    // Forgets the mutations of the previous generation
+    pub fn start_generation(&mut self) {
+        self.connections.clear();
+        self.split_connections.clear();
+    }
+
+    fn new_innovation_number(&mut self) -> u64 {
+        let innovation_number = self.next_innovation_number;
+        self.next_innovation_number += 1;
+        innovation_number
+    }
+
+    // Innovation number of a connection from `in_node` to `out_node`
+    pub fn connection(&mut self, in_node: usize, out_node: usize) -> u64 {
+        if let Some(innovation_number) = self.connections.get(&(in_node, out_node)) {
+            return *innovation_number;
+        }
+        let innovation_number = self.new_innovation_number();
+        self.connections
+            .insert((in_node, out_node), innovation_number);
+        innovation_number
+    }
+
+    // New node and connections used to split the connection with the given
+    // innovation number
+    pub fn split_connection(
+        &mut self,
+        innovation_number: u64,
+        in_node: usize,
+        out_node: usize,
+    ) -> SplitConnection {
+        if let Some(split) = self.split_connections.get(&innovation_number) {
+            return *split;
+        }
+        let node = self.next_node_id;
+        self.next_node_id += 1;
+        let split = SplitConnection {
+            node,
+            in_innovation_number: self.connection(in_node, node),
+            out_innovation_number: self.connection(node, out_node),
+        };
+        self.split_connections.insert(innovation_number, split);
+        split
+    }
+}
@@ -1,40 +1,36 @@
+mod adapter;
+mod controller;
+mod fitness;
 mod game_state;
-
-use self::game_state::GameState;
+mod innovation;
+mod observation;
+mod snapshot;
+
+pub use self::adapter::{Game, GameAdapter};
+pub use self::controller::Controller;
+pub use self::fitness::{BuiltinFitness, FitnessFunction, Outcome};
+pub use self::game_state::{GameState, PowerUp, SuperMarioBros};
+pub use self::observation::{Observation, PixelColours};
+pub use self::snapshot::SnapshotError;
+
+use self::innovation::InnovationRegistry;
+use crate::emulator::Emulator;
+use crate::nes::input::{Button, GamepadState};
 use crate::nes::{cpu, mem};
-use crate::utils::{Screen, Tile, SCREEN_SIZE};
+use crate::utils::{Screen, Tile, View};
 
+use crossbeam::channel::unbounded;
 use rand::distributions::{Distribution, Uniform};
 use rand::seq::SliceRandom;
-use rand::Rng;
-use serde::{Deserialize, Serialize};
+use rand::{Rng, SeedableRng};
+use rand_distr::Normal;
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Deserializer, Serialize};
 
 use std::cmp::Reverse;
-use std::collections::{HashMap, HashSet};
-use std::time::Instant;
-
-const INPUT_NODES: usize = SCREEN_SIZE * SCREEN_SIZE;
-const OUTPUT_NODES: usize = 2;
-
-const DESIRED_POPULATION: i64 = 300;
-
-// Maximum number of species in pool before weaker species are removed
-const MAX_SPECIES: usize = 20;
-
-// Maximum number of generations that a species can exist for without
-// improving its performance
-const MAX_SPECIES_STALENESS: u64 = 10;
-
-// Threshold below which a compatibility distance implies that two individuals
-// are of the same species
-const COMPATIBILITY_THRESHOLD: f64 = 2.0;
-// Coefficients used when calculating compatibility distance of two individuals.
-// While the NEAT paper distinguishes between 'excess' and 'disjoint' genes,
-// here we use the term 'disjoint' to refer to both
-const DISJOINT_COEFFICIENT: f64 = 0.4;
-const WEIGHTS_COEFFICIENT: f64 = 0.1;
-
-const MUTATION_PROBABILITY: f64 = 0.3;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 impl Tile {
     fn as_nn_input(self) -> f64 {
@@ -42,21 +38,279 @@ impl Tile {
 
         match self {
             Nothing | Mario => 0.0,
-            Block => 1.0,
+            // Blocks are positive, ranging down to the ones worth hitting
+            // and to pickups, while pits are negative like enemies
+            Solid => 1.0,
+            Breakable => 0.9,
+            Pipe => 0.8,
+            Question => 0.7,
+            PowerUp => 0.6,
+            Coin => 0.3,
+            Pit => -0.2,
+            // Every kind of enemy is negative
             Enemy => -1.0,
+            Hazard => -0.8,
+            Projectile => -0.6,
+            Boss => -0.4,
         }
     }
 }
 
-#[derive(Copy, Clone)]
+// Inputs fed to the network besides the tiles around Mario, one input node
+// each. Apart from the bias, their values come from the game adapter
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ScalarInput {
+    // Always 1, so that networks can shift their nodes' activations
+    Bias,
+    HorizontalSpeed,
+    VerticalSpeed,
+    OnGround,
+    // Fraction of the level left to cover, given the x coordinate of the end
+    // of the level of each save state, in the same order. A single value is
+    // used for every level
+    DistanceToLevelEnd {
+        #[serde(deserialize_with = "one_or_many")]
+        level_end_x: Vec<u16>,
+    },
+    // 0 when small, 0.5 when big and 1 with the fire flower
+    PowerUp,
+    // Fraction of the in-game time left
+    Timer,
+}
+
+// Accepts either a single value or a list of them, as configs and snapshots
+// written before `DistanceToLevelEnd` was set per level have a single
+// `level_end_x`
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u16>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(u16),
+        Many(Vec<u16>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+impl ScalarInput {
+    // Values are scaled to roughly [-1, 1], like the tile inputs. `level` is
+    // the index of the save state being played
+    fn value(&self, adapter: &dyn GameAdapter, game_state: &GameState, level: usize) -> f64 {
+        match self {
+            ScalarInput::Bias => 1.0,
+            _ => adapter.scalar_input(self, game_state, level),
+        }
+    }
+}
+
+// Buttons pressed by the network, one per output node
 pub struct Inputs {
-    pub right: bool,
-    pub a: bool,
+    pub buttons: Vec<(Button, bool)>,
+}
+
+impl Inputs {
+    pub fn apply(&self, gamepad: &mut GamepadState) {
+        for &(button, pressed) in &self.buttons {
+            gamepad.set(button, pressed);
+        }
+    }
 }
 
+// Timeouts are counted in emulated frames so that an individual gets the same
+// fitness regardless of host speed or emulation speed
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct AiOptions {
-    pub stuck_timeout_ms: u64,
-    pub finish_timeout_ms: u64,
+    // Game being played, which decides how its state is read and when a run
+    // ends
+    pub game: Game,
+    pub stuck_timeout_frames: u64,
+    pub finish_timeout_frames: u64,
+    // Extra frames added to the finish timeout for every block (16 pixels) of
+    // new ground covered. 0 disables the bonus
+    pub timeout_bonus_frames_per_block: u64,
+    // Tiles around Mario fed to the network, one input node each. They are
+    // followed by one input node per scalar input
+    pub view: View,
+    // Whether the network sees the view as tiles or as pixels
+    pub observation: Observation,
+    // Inputs fed to the network on top of the tile grid
+    pub inputs: Vec<ScalarInput>,
+    // Buttons controlled by the network, one output node each
+    pub outputs: Vec<Button>,
+    pub mutation: MutationConfig,
+    // Stop training once this many generations have been evaluated
+    pub max_generations: Option<u64>,
+    // Allow connections to form cycles, and carry hidden and output node
+    // activations over from one frame to the next, giving networks a memory
+    pub recurrent: bool,
+    // Seed for all of the randomness used by evolution. Runs with the same
+    // seed are identical. A random seed is picked (and printed) if None
+    pub seed: Option<u64>,
+    pub evolution: EvolutionConfig,
+    // Directory that a snapshot of the population is saved to after every
+    // generation
+    pub snapshot_directory: String,
+    // How the fitness of a single run on a level is computed
+    pub fitness: BuiltinFitness,
+    // How the fitnesses an individual gets on each level are combined
+    pub fitness_aggregation: FitnessAggregation,
+}
+
+impl Default for AiOptions {
+    fn default() -> Self {
+        Self {
+            game: Game::SuperMarioBros,
+            stuck_timeout_frames: 30,
+            finish_timeout_frames: 1200,
+            timeout_bonus_frames_per_block: 0,
+            view: View::default(),
+            observation: Observation::Tiles,
+            // The flagpole in World 1-1 is at x = 3168
+            inputs: vec![
+                ScalarInput::Bias,
+                ScalarInput::HorizontalSpeed,
+                ScalarInput::VerticalSpeed,
+                ScalarInput::OnGround,
+                ScalarInput::DistanceToLevelEnd {
+                    level_end_x: vec![3168],
+                },
+            ],
+            outputs: vec![Button::Right, Button::A],
+            mutation: MutationConfig::default(),
+            max_generations: None,
+            recurrent: false,
+            seed: None,
+            evolution: EvolutionConfig::default(),
+            snapshot_directory: "snapshots".to_string(),
+            fitness: BuiltinFitness::Distance,
+            fitness_aggregation: FitnessAggregation::Mean,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum FitnessAggregation {
+    Mean,
+    // Rewards individuals that do well on every level rather than on most
+    Minimum,
+    Sum,
+}
+
+impl FitnessAggregation {
+    pub fn aggregate(self, fitnesses: &[u64]) -> u64 {
+        let sum = fitnesses.iter().sum::<u64>();
+        match self {
+            FitnessAggregation::Mean => sum / (fitnesses.len() as u64).max(1),
+            FitnessAggregation::Minimum => *fitnesses.iter().min().unwrap_or(&0),
+            FitnessAggregation::Sum => sum,
+        }
+    }
+}
+
+// Population size, speciation and crossover settings
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvolutionConfig {
+    pub population: usize,
+    // Maximum number of species in pool before weaker species are removed
+    pub max_species: usize,
+    // Maximum number of generations that a species can exist for without
+    // improving its performance
+    pub max_species_staleness: u64,
+    // Threshold below which a compatibility distance implies that two
+    // individuals are of the same species
+    pub compatibility_threshold: f64,
+    // Coefficients used when calculating compatibility distance of two
+    // individuals. While the NEAT paper distinguishes between 'excess' and
+    // 'disjoint' genes, here we use the term 'disjoint' to refer to both
+    pub disjoint_coefficient: f64,
+    pub weights_coefficient: f64,
+    // Probability that a child produced by crossover is mutated. Children
+    // that are copies of a single parent are always mutated
+    pub crossover_mutation_probability: f64,
+    // Probability that a child's second parent is taken from another species
+    pub interspecies_crossover_probability: f64,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        Self {
+            population: 300,
+            max_species: 20,
+            max_species_staleness: 10,
+            compatibility_threshold: 2.0,
+            disjoint_coefficient: 0.4,
+            weights_coefficient: 0.1,
+            crossover_mutation_probability: 0.3,
+            interspecies_crossover_probability: 0.01,
+        }
+    }
+}
+
+// Rates at which a mutated child's genes and structure are changed. The weight
+// rates apply to each gene separately, while the other rates are the
+// probability of making that change once. Several mutations can happen to the
+// same child
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MutationConfig {
+    // Nudge a weight by a value drawn from a normal distribution
+    pub weight_perturbation_rate: f64,
+    pub weight_perturbation_std_dev: f64,
+    // Replace a weight with a new random weight
+    pub weight_replacement_rate: f64,
+    pub add_connection_rate: f64,
+    pub add_node_rate: f64,
+    // Enable a disabled gene, or disable an enabled one
+    pub toggle_gene_rate: f64,
+    pub delete_connection_rate: f64,
+    // Remove a hidden node along with its connections
+    pub prune_node_rate: f64,
+    pub change_activation_rate: f64,
+    // New weights are drawn uniformly from [-initial_weight_range,
+    // initial_weight_range]. Must be positive
+    pub initial_weight_range: f64,
+    // Weights are clamped to [-max_weight, max_weight]
+    pub max_weight: f64,
+}
+
+impl Default for MutationConfig {
+    fn default() -> Self {
+        Self {
+            weight_perturbation_rate: 0.8,
+            weight_perturbation_std_dev: 0.5,
+            weight_replacement_rate: 0.1,
+            add_connection_rate: 0.3,
+            add_node_rate: 0.1,
+            toggle_gene_rate: 0.05,
+            delete_connection_rate: 0.05,
+            prune_node_rate: 0.02,
+            change_activation_rate: 0.1,
+            initial_weight_range: 2.0,
+            max_weight: 8.0,
+        }
+    }
+}
+
+impl MutationConfig {
+    fn random_weight(&self, rng: &mut Pcg64) -> f64 {
+        rng.gen_range(-self.initial_weight_range, self.initial_weight_range)
+    }
+
+    fn clamp_weight(&self, weight: f64) -> f64 {
+        weight.max(-self.max_weight).min(self.max_weight)
+    }
+}
+
+// Outcome of evaluating a whole generation in parallel
+pub enum GenerationResult {
+    Completed,
+    Succeeded,
+    Interrupted,
 }
 
 // TODO: Find better name for this
@@ -68,62 +322,140 @@ enum XState {
     Succeeded,
 }
 
+#[derive(Copy, Clone)]
 struct IndividualStateOptions {
-    stuck_timeout_ms: u64,
-    finish_timeout_ms: u64,
+    game: Game,
+    stuck_timeout_frames: u64,
+    finish_timeout_frames: u64,
+    timeout_bonus_frames_per_block: u64,
+    recurrent: bool,
+    view: View,
+    observation: Observation,
+}
+
+impl From<&AiOptions> for IndividualStateOptions {
+    fn from(options: &AiOptions) -> Self {
+        Self {
+            game: options.game,
+            stuck_timeout_frames: options.stuck_timeout_frames,
+            finish_timeout_frames: options.finish_timeout_frames,
+            timeout_bonus_frames_per_block: options.timeout_bonus_frames_per_block,
+            recurrent: options.recurrent,
+            view: options.view,
+            observation: options.observation,
+        }
+    }
 }
 
 struct IndividualState {
     game_state: GameState,
+    // Game state on every frame played so far, for the fitness function
+    game_states: Vec<GameState>,
     previous_game_state: GameState,
     screen: Screen,
+    // What the network sees of the view, one value per cell
+    observation: Vec<f64>,
     state: XState,
-    start: Instant,
-    last_x: u16,
-    last_x_update: Instant,
-    stuck_timeout_ms: u64,
-    finish_timeout_ms: u64,
+    // Number of frames played so far
+    frames: u64,
+    last_progress: u64,
+    last_progress_update: u64,
+    max_progress: u64,
+    timeout_bonus_frames: u64,
+    // Node activations, which persist between frames in recurrent mode
+    activations: HashMap<usize, f64>,
+    options: IndividualStateOptions,
+    // Index of the save state being played
+    level: usize,
 }
 
 impl IndividualState {
-    pub fn new(options: IndividualStateOptions) -> Self {
+    pub fn new(options: IndividualStateOptions, level: usize) -> Self {
         Self {
             game_state: GameState::default(),
+            game_states: vec![],
             previous_game_state: GameState::default(),
             screen: Screen::default(),
+            observation: vec![0.0; options.view.tiles()],
             state: XState::Playing,
-            start: Instant::now(),
-            last_x: 0,
-            last_x_update: Instant::now(),
-            stuck_timeout_ms: options.stuck_timeout_ms,
-            finish_timeout_ms: options.finish_timeout_ms,
+            frames: 0,
+            last_progress: 0,
+            last_progress_update: 0,
+            max_progress: 0,
+            timeout_bonus_frames: 0,
+            activations: HashMap::new(),
+            options,
+            level,
+        }
+    }
+
+    fn update_timeout_bonus(&mut self, progress: u64) {
+        const BLOCK_SIZE: u64 = 16;
+
+        if self.frames == 1 {
+            self.max_progress = progress;
+        } else if progress / BLOCK_SIZE > self.max_progress / BLOCK_SIZE {
+            let new_blocks = progress / BLOCK_SIZE - self.max_progress / BLOCK_SIZE;
+            self.timeout_bonus_frames += new_blocks * self.options.timeout_bonus_frames_per_block;
+            self.max_progress = progress;
         }
     }
 
     fn update_state(&mut self) {
         use self::XState::*;
 
-        let is_moving = self.game_state.mario_x != self.last_x;
-        let is_stuck =
-            !is_moving && self.last_x_update.elapsed().as_millis() > self.stuck_timeout_ms.into();
-        let took_too_long = self.start.elapsed().as_millis() > self.finish_timeout_ms.into();
+        let adapter = self.options.game.adapter();
+        let progress = adapter.progress(&self.game_state);
+        self.frames += 1;
+        self.update_timeout_bonus(progress);
+
+        let is_moving = progress != self.last_progress;
+        let is_stuck = !is_moving
+            && self.frames - self.last_progress_update > self.options.stuck_timeout_frames;
+        let took_too_long =
+            self.frames > self.options.finish_timeout_frames + self.timeout_bonus_frames;
 
-        if self.game_state.lives < self.previous_game_state.lives {
-            self.state = Dead;
-        } else if self.game_state.level > self.previous_game_state.level {
-            self.state = Succeeded;
+        // There is no previous state to compare with on the first frame
+        let outcome = if self.frames > 1 {
+            adapter.outcome(&self.previous_game_state, &self.game_state)
+        } else {
+            None
+        };
+
+        if let Some(outcome) = outcome {
+            self.state = match outcome {
+                Outcome::Stuck => Stuck,
+                Outcome::Dead => Dead,
+                Outcome::Succeeded => Succeeded,
+            };
         } else if is_stuck || took_too_long {
             self.state = Stuck;
         } else if is_moving {
-            self.last_x = self.game_state.mario_x;
-            self.last_x_update = Instant::now();
+            self.last_progress = progress;
+            self.last_progress_update = self.frames;
         }
     }
 
-    pub fn update(&mut self, mut cpu: &mut cpu::Cpu<mem::MemMap>) {
+    pub fn update(&mut self, cpu: &mut cpu::Cpu<mem::MemMap>) {
         self.previous_game_state = self.game_state;
-        self.game_state = game_state::get_state(&mut cpu);
-        self.screen = game_state::get_screen(&mut cpu, self.game_state);
+        let adapter = self.options.game.adapter();
+        self.game_state = adapter.read_state(cpu);
+        self.game_states.push(self.game_state);
+        self.screen = adapter.read_screen(cpu, &self.game_state, self.options.view);
+        self.observation = match self.options.observation {
+            Observation::Tiles => self
+                .screen
+                .iter()
+                .flat_map(|row| row.iter().map(|tile| tile.as_nn_input()))
+                .collect(),
+            Observation::Pixels { cell_size, colours } => observation::pixels(
+                &cpu.mem.ppu.screen[..],
+                adapter.player_screen_position(&self.game_state),
+                self.options.view,
+                cell_size,
+                colours,
+            ),
+        };
 
         self.update_state();
     }
@@ -147,12 +479,21 @@ impl IndividualState {
     }
 
     pub fn get_screen(&self) -> Screen {
-        self.screen
+        self.screen.clone()
+    }
+
+    // Runs that were stopped before they ended count as stuck
+    pub fn outcome(&self) -> Outcome {
+        match self.state {
+            XState::Dead => Outcome::Dead,
+            XState::Succeeded => Outcome::Succeeded,
+            XState::Playing | XState::Stuck => Outcome::Stuck,
+        }
     }
 
-    pub fn fitness(&self) -> u64 {
-        let success_bonus = if self.has_succeeded() { 1000 } else { 0 };
-        self.game_state.mario_x as u64 + success_bonus
+    pub fn fitness(&self, fitness_function: &dyn FitnessFunction) -> u64 {
+        let adapter = self.options.game.adapter();
+        fitness_function.fitness(adapter, &self.game_states, self.outcome())
     }
 }
 
@@ -163,16 +504,69 @@ enum NodeType {
     Output,
 }
 
+// Function applied to the weighted sum of a node's incoming connections
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+enum Activation {
+    Sigmoid,
+    Tanh,
+    Relu,
+    Step,
+    Gaussian,
+    Identity,
+}
+
+impl Activation {
+    const ALL: [Activation; 6] = [
+        Activation::Sigmoid,
+        Activation::Tanh,
+        Activation::Relu,
+        Activation::Step,
+        Activation::Gaussian,
+        Activation::Identity,
+    ];
+
+    fn apply(self, x: f64) -> f64 {
+        use self::Activation::*;
+
+        match self {
+            Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Tanh => x.tanh(),
+            Relu => x.max(0.0),
+            Step => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Gaussian => (-x * x).exp(),
+            Identity => x,
+        }
+    }
+}
+
+// Input nodes ignore their activation function, as their values are set
+// directly from the game
 #[derive(Copy, Clone, Serialize, Deserialize)]
-struct Node(NodeType);
+struct Node {
+    node_type: NodeType,
+    activation: Activation,
+}
 
 impl Node {
-    fn is_output_node(&self) -> bool {
-        self.0 == NodeType::Output
+    fn new(node_type: NodeType) -> Self {
+        Self {
+            node_type,
+            activation: Activation::Sigmoid,
+        }
     }
 
-    fn is_hidden_node(&self) -> bool {
-        self.0 == NodeType::Hidden
+    fn is_input_node(&self) -> bool {
+        self.node_type == NodeType::Input
+    }
+
+    fn is_output_node(&self) -> bool {
+        self.node_type == NodeType::Output
     }
 }
 
@@ -185,36 +579,54 @@ struct Gene {
     innovation_number: u64,
 }
 
+// Node ids are shared by the whole population: input nodes come first,
+// followed by output nodes and then by hidden nodes, whose ids are handed out
+// by the innovation registry
 #[derive(Default, Clone, Serialize, Deserialize)]
 struct Individual {
-    nodes: Vec<Node>,
+    nodes: BTreeMap<usize, Node>,
     genes: Vec<Gene>,
     fitness: u64,
+    // Fitness on each level, before aggregation
+    #[serde(default)]
+    level_fitnesses: Vec<u64>,
 }
 
 impl Individual {
-    pub fn new(max_innovation_number: u64) -> Self {
-        let mut nodes = vec![Node(NodeType::Input); INPUT_NODES];
-        nodes.append(&mut vec![Node(NodeType::Output); OUTPUT_NODES]);
-        let mut rng = rand::thread_rng();
-        let input_distribution = Uniform::from(0..INPUT_NODES);
-        let output_distribution = Uniform::from(INPUT_NODES..(INPUT_NODES + OUTPUT_NODES));
-        let genes = vec![
-            Gene {
-                in_node: input_distribution.sample(&mut rng),
-                out_node: output_distribution.sample(&mut rng),
-                innovation_number: max_innovation_number,
-                weight: 1.0,
-                enabled: true,
-            },
-            Gene {
-                in_node: input_distribution.sample(&mut rng),
-                out_node: output_distribution.sample(&mut rng),
-                innovation_number: max_innovation_number + 1,
-                weight: 1.0,
-                enabled: true,
+    pub fn new(
+        input_nodes: usize,
+        output_nodes: usize,
+        mutation: &MutationConfig,
+        innovations: &mut InnovationRegistry,
+        rng: &mut Pcg64,
+    ) -> Self {
+        let mut nodes = BTreeMap::new();
+        for id in 0..input_nodes {
+            nodes.insert(id, Node::new(NodeType::Input));
+        }
+        for id in input_nodes..(input_nodes + output_nodes) {
+            nodes.insert(id, Node::new(NodeType::Output));
+        }
+        let input_distribution = Uniform::from(0..input_nodes);
+        let output_distribution = Uniform::from(input_nodes..(input_nodes + output_nodes));
+        let mut genes: Vec<Gene> = vec![];
+        for _ in 0..2 {
+            let in_node = input_distribution.sample(rng);
+            let out_node = output_distribution.sample(rng);
+            if genes
+                .iter()
+                .any(|gene| gene.in_node == in_node && gene.out_node == out_node)
+            {
+                continue;
             }
-        ];
+            genes.push(Gene {
+                in_node,
+                out_node,
+                innovation_number: innovations.connection(in_node, out_node),
+                weight: mutation.random_weight(rng),
+                enabled: true,
+            });
+        }
 
         Self {
             nodes,
@@ -223,18 +635,86 @@ impl Individual {
         }
     }
 
-    fn sigmoid(x: f64) -> f64 {
-        1.0 / (1.0 + (-x).exp())
-    }
-
     pub fn enabled_genes_mut(&mut self) -> Vec<&mut Gene> {
         self.genes.iter_mut().filter(|g| g.enabled).collect()
     }
 
-    pub fn evaluate(&self, input: [f64; INPUT_NODES]) -> (f64, f64) {
-        let mut nodes = vec![0.0; self.nodes.len()];
-        for i in 0..INPUT_NODES {
-            nodes[i] = input[i];
+    fn has_connection(&self, in_node: usize, out_node: usize) -> bool {
+        self.genes
+            .iter()
+            .any(|gene| gene.in_node == in_node && gene.out_node == out_node)
+    }
+
+    // Returns true if `to` can be reached from `from` by following genes
+    fn has_path(&self, from: usize, to: usize) -> bool {
+        let mut stack = vec![from];
+        let mut visited = vec![];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                return true;
+            }
+            if visited.contains(&node) {
+                continue;
+            }
+            visited.push(node);
+            for gene in &self.genes {
+                if gene.in_node == node {
+                    stack.push(gene.out_node);
+                }
+            }
+        }
+        false
+    }
+
+    // Hidden and output nodes, ordered so that every node comes after the
+    // nodes it takes input from. Nodes that are part of a cycle (in recurrent
+    // networks) come last, in id order, and read the previous frame's
+    // activations of the nodes that come after them
+    fn evaluation_order(&self) -> Vec<usize> {
+        let mut in_degrees: BTreeMap<usize, usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !node.is_input_node())
+            .map(|(id, _)| (*id, 0))
+            .collect();
+        let genes: Vec<&Gene> = self
+            .genes
+            .iter()
+            .filter(|gene| gene.enabled && !self.nodes[&gene.in_node].is_input_node())
+            .collect();
+        for gene in &genes {
+            *in_degrees.get_mut(&gene.out_node).unwrap() += 1;
+        }
+        let mut ready: Vec<usize> = in_degrees
+            .iter()
+            .filter(|(_, in_degree)| **in_degree == 0)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut order = vec![];
+        while let Some(node) = ready.pop() {
+            order.push(node);
+            for gene in genes.iter().filter(|gene| gene.in_node == node) {
+                let in_degree = in_degrees.get_mut(&gene.out_node).unwrap();
+                *in_degree -= 1;
+                if *in_degree == 0 {
+                    ready.push(gene.out_node);
+                }
+            }
+        }
+        for (id, in_degree) in in_degrees {
+            if in_degree > 0 {
+                order.push(id);
+            }
+        }
+        order
+    }
+
+    // Returns the activations of the output nodes. Nodes that have not been
+    // evaluated yet are read from `nodes`, which holds the previous
+    // activations in recurrent mode
+    pub fn evaluate(&self, input: &[f64], nodes: &mut HashMap<usize, f64>) -> Vec<f64> {
+        for (i, value) in input.iter().enumerate() {
+            nodes.insert(i, *value);
         }
         let mut incoming = HashMap::new();
         for gene in &self.genes {
@@ -244,23 +724,96 @@ impl Individual {
             let entry = incoming.entry(gene.out_node).or_insert(vec![]);
             entry.push((gene.in_node, gene.weight));
         }
-        for i in (INPUT_NODES + OUTPUT_NODES)..self.nodes.len() {
+        for i in self.evaluation_order() {
             if let Some(incoming) = incoming.get(&i) {
-                let sum = incoming
-                    .iter()
-                    .fold(0.0, |acc, (node, weight)| acc + (nodes[*node] * weight));
-                nodes[i] = Self::sigmoid(sum);
+                let sum = incoming.iter().fold(0.0, |acc, (node, weight)| {
+                    acc + (nodes.get(node).unwrap_or(&0.0) * weight)
+                });
+                nodes.insert(i, self.nodes[&i].activation.apply(sum));
             }
         }
-        for i in INPUT_NODES..(INPUT_NODES + OUTPUT_NODES) {
-            if let Some(incoming) = incoming.get(&i) {
-                let sum = incoming
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.is_output_node())
+            .map(|(id, _)| *nodes.get(id).unwrap_or(&0.0))
+            .collect()
+    }
+
+    pub fn get_inputs(
+        &self,
+        state: &mut IndividualState,
+        inputs: &[ScalarInput],
+        outputs: &[Button],
+    ) -> Inputs {
+        const THRESHOLD: f64 = 0.5;
+
+        if !state.options.recurrent {
+            state.activations.clear();
+        }
+        let input = {
+            let mut input = state.observation.clone();
+            let adapter = state.options.game.adapter();
+            input.extend(
+                inputs
                     .iter()
-                    .fold(0.0, |acc, (node, weight)| acc + (nodes[*node] * weight));
-                nodes[i] = Self::sigmoid(sum);
+                    .map(|input| input.value(adapter, &state.game_state, state.level)),
+            );
+            input
+        };
+        let values = self.evaluate(&input, &mut state.activations);
+
+        Inputs {
+            buttons: outputs
+                .iter()
+                .zip(values)
+                .map(|(button, value)| (*button, value > THRESHOLD))
+                .collect(),
+        }
+    }
+
+    // Plays the individual on the emulator from the save state of the given
+    // level until it dies, gets stuck or succeeds. Returns None if `on_frame`
+    // asked to stop early
+    fn play(
+        &self,
+        emulator: &mut Emulator,
+        level: usize,
+        options: IndividualStateOptions,
+        inputs: &[ScalarInput],
+        outputs: &[Button],
+        on_frame: &dyn Fn(Screen) -> bool,
+    ) -> Option<IndividualState> {
+        emulator.reset(level);
+        let mut state = IndividualState::new(options, level);
+        loop {
+            emulator.step_frame();
+            state.update(&mut emulator.cpu);
+            if state.is_stuck() || state.is_dead() || state.has_succeeded() {
+                return Some(state);
+            }
+
+            let buttons = self.get_inputs(&mut state, inputs, outputs);
+            buttons.apply(&mut emulator.cpu.mem.input.gamepad);
+
+            if !on_frame(state.get_screen()) {
+                return None;
             }
         }
-        (nodes[INPUT_NODES], nodes[INPUT_NODES + 1])
+    }
+
+    // Plays every level in turn. Returns None if `on_frame` asked to stop
+    // early
+    fn play_levels(
+        &self,
+        emulator: &mut Emulator,
+        options: IndividualStateOptions,
+        inputs: &[ScalarInput],
+        outputs: &[Button],
+        on_frame: &dyn Fn(Screen) -> bool,
+    ) -> Option<Vec<IndividualState>> {
+        (0..emulator.levels())
+            .map(|level| self.play(emulator, level, options, inputs, outputs, on_frame))
+            .collect()
     }
 }
 
@@ -281,12 +834,18 @@ impl Species {
         self.members
             .sort_by_key(|individual| Reverse(individual.fitness));
     }
-}
 
-#[derive(Serialize, Deserialize)]
-struct AiSnapshot {
-    pool: Vec<Species>,
-    generation: u64,
+    // Sum of the members' adjusted fitnesses, i.e. of their fitnesses divided
+    // by the size of the species (explicit fitness sharing from the NEAT
+    // paper). This is the same as the species' average fitness, and keeps a
+    // single large species from taking over the population
+    fn adjusted_fitness(&self) -> f64 {
+        let species_size = self.len() as f64;
+        self.members
+            .iter()
+            .map(|individual| individual.fitness as f64 / species_size)
+            .sum()
+    }
 }
 
 pub struct Ai {
@@ -296,226 +855,569 @@ pub struct Ai {
     // (species_index, individual_index)
     current_individual: (usize, usize),
     current_individual_state: IndividualState,
-    stuck_timeout_ms: u64,
-    finish_timeout_ms: u64,
+    individual_state_options: IndividualStateOptions,
+    max_generations: Option<u64>,
+    rng: Pcg64,
+    innovations: InnovationRegistry,
+    inputs: Vec<ScalarInput>,
+    outputs: Vec<Button>,
+    mutation: MutationConfig,
+    evolution: EvolutionConfig,
+    seed: u64,
+    snapshot_directory: PathBuf,
+    // Fitness of the last individual exported as the champion
+    champion_fitness: u64,
+    fitness: BuiltinFitness,
+    fitness_function: Arc<dyn FitnessFunction + Send + Sync>,
+    fitness_aggregation: FitnessAggregation,
+    // Level being played by the current individual, and its fitness on the
+    // levels it has already played
+    current_level: usize,
+    current_level_fitnesses: Vec<u64>,
+    current_levels_succeeded: bool,
+    // Best fitness on each level during the last generation
+    best_level_fitnesses: Vec<u64>,
 }
 
 impl Ai {
-    pub fn new(options: AiOptions) -> Self {
+    // An AI with an empty pool
+    fn with_options(options: AiOptions, seed: u64) -> Self {
+        let individual_state_options = IndividualStateOptions::from(&options);
         Self {
-            pool: vec![
-                Species {
-                    id: 0,
-                    members: vec![Individual::new(1)],
-                    ..Species::default()
-                },
-                Species {
-                    id: 1,
-                    members: vec![Individual::new(4)],
-                    ..Species::default()
-                },
-                Species {
-                    id: 2,
-                    members: vec![Individual::new(7)],
-                    ..Species::default()
-                },
-            ],
+            pool: vec![],
             generation: 0,
             max_fitness: 0,
             current_individual: (0, 0),
-            current_individual_state: IndividualState::new(IndividualStateOptions {
-                stuck_timeout_ms: options.stuck_timeout_ms,
-                finish_timeout_ms: options.finish_timeout_ms,
-            }),
-            stuck_timeout_ms: options.stuck_timeout_ms,
-            finish_timeout_ms: options.finish_timeout_ms,
+            current_individual_state: IndividualState::new(individual_state_options, 0),
+            individual_state_options,
+            max_generations: options.max_generations,
+            rng: Pcg64::seed_from_u64(seed),
+            innovations: InnovationRegistry::new(
+                options.view.tiles() + options.inputs.len() + options.outputs.len(),
+            ),
+            inputs: options.inputs,
+            outputs: options.outputs,
+            mutation: options.mutation,
+            evolution: options.evolution,
+            seed,
+            snapshot_directory: PathBuf::from(options.snapshot_directory),
+            champion_fitness: 0,
+            fitness: options.fitness,
+            fitness_function: Arc::new(options.fitness),
+            fitness_aggregation: options.fitness_aggregation,
+            current_level: 0,
+            current_level_fitnesses: vec![],
+            current_levels_succeeded: true,
+            best_level_fitnesses: vec![],
+        }
+    }
+
+    // The options the AI is running with
+    pub fn options(&self) -> AiOptions {
+        let individual_state_options = self.individual_state_options;
+        AiOptions {
+            game: individual_state_options.game,
+            stuck_timeout_frames: individual_state_options.stuck_timeout_frames,
+            finish_timeout_frames: individual_state_options.finish_timeout_frames,
+            timeout_bonus_frames_per_block: individual_state_options.timeout_bonus_frames_per_block,
+            view: individual_state_options.view,
+            observation: individual_state_options.observation,
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            mutation: self.mutation,
+            max_generations: self.max_generations,
+            recurrent: individual_state_options.recurrent,
+            seed: Some(self.seed),
+            evolution: self.evolution,
+            snapshot_directory: self.snapshot_directory.to_string_lossy().into_owned(),
+            fitness: self.fitness,
+            fitness_aggregation: self.fitness_aggregation,
         }
     }
 
-    fn cross_over(a: &Individual, b: &Individual) -> Individual {
+    pub fn new(options: AiOptions) -> Self {
+        let seed = options.seed.unwrap_or_else(rand::random);
+        println!("Random seed: {}", seed);
+        let mut ai = Self::with_options(options, seed);
+        let input_nodes = ai.individual_state_options.view.tiles() + ai.inputs.len();
+        for _ in 0..ai.evolution.population {
+            let individual = Individual::new(
+                input_nodes,
+                ai.outputs.len(),
+                &ai.mutation,
+                &mut ai.innovations,
+                &mut ai.rng,
+            );
+            ai.add_to_pool(individual);
+        }
+        ai
+    }
+
+    fn cross_over(a: &Individual, b: &Individual, rng: &mut Pcg64) -> Individual {
         // Ensure A is fitter than B
         let (a, b) = if a.fitness < b.fitness {
             (b, a)
         } else {
             (a, b)
         };
-        let a_innos: HashSet<_> = a.genes.iter().map(|g| g.innovation_number).collect();
-        let b_innos: HashSet<_> = b.genes.iter().map(|g| g.innovation_number).collect();
-        let mut matching_genes: HashMap<_, _> = a_innos
-            .intersection(&b_innos)
-            .cloned()
-            .map(|i| (i, (None, None)))
-            .collect();
-        let mut disjoint_genes: HashMap<_, _> = a_innos
-            .difference(&b_innos)
-            .cloned()
-            .map(|i| (i, None))
+        let b_genes: HashMap<_, _> = b
+            .genes
+            .iter()
+            .map(|gene| (gene.innovation_number, gene))
             .collect();
-        for gene in &a.genes {
-            matching_genes
-                .entry(gene.innovation_number)
-                .and_modify(|(g, _)| *g = Some(gene));
-            disjoint_genes
-                .entry(gene.innovation_number)
-                .and_modify(|g| *g = Some(gene));
-        }
-        for gene in &b.genes {
-            matching_genes
-                .entry(gene.innovation_number)
-                .and_modify(|(_, g)| *g = Some(gene));
-        }
-        let mut genes: Vec<Gene> = matching_genes
-            .values()
-            .map(|(g_a, g_b)| {
-                if rand::random() {
-                    (*g_a.unwrap()).clone()
-                } else {
-                    (*g_b.unwrap()).clone()
-                }
+        // Matching genes are inherited randomly from either parent, while
+        // disjoint genes are inherited from the fitter parent. Genes are
+        // visited in A's order so that a given RNG state always produces the
+        // same child
+        let genes = a
+            .genes
+            .iter()
+            .map(|gene_a| match b_genes.get(&gene_a.innovation_number) {
+                Some(gene_b) if rng.gen() => **gene_b,
+                _ => *gene_a,
             })
             .collect();
-        let mut disjoint_genes = disjoint_genes
-            .values()
-            .map(|g| (*g.unwrap()).clone())
+        // Nodes are taken from A too, but nodes that both parents share
+        // inherit their activation function from either of them
+        let nodes = a
+            .nodes
+            .iter()
+            .map(|(id, node_a)| match b.nodes.get(id) {
+                Some(node_b) if rng.gen() => (*id, *node_b),
+                _ => (*id, *node_a),
+            })
             .collect();
-        genes.append(&mut disjoint_genes);
         Individual {
-            nodes: a.nodes.clone(),
+            nodes,
             genes,
             ..Individual::default()
         }
     }
 
-    fn mutate_add_connection(individual: &mut Individual, max_innovation_number: u64) -> u64 {
-        let mut rng = rand::thread_rng();
-        let in_distribution = Uniform::from(0..individual.nodes.len());
-        let mut in_node = in_distribution.sample(&mut rng);
-        while individual.nodes[in_node].is_output_node() || in_node == individual.nodes.len() - 1 {
-            in_node = in_distribution.sample(&mut rng);
-        }
-        let out_distribution = Uniform::from(INPUT_NODES..individual.nodes.len());
-        let mut out_node = out_distribution.sample(&mut rng);
-        // To prevent cycles, ensure out node comes after in node if out node is
-        // hidden node (output nodes come before hidden nodes, so only perform
-        // check for hidden nodes)
-        while individual.nodes[out_node].is_hidden_node() && out_node < in_node {
-            out_node = out_distribution.sample(&mut rng);
+    fn mutate_add_connection(
+        individual: &mut Individual,
+        innovations: &mut InnovationRegistry,
+        rng: &mut Pcg64,
+        mutation: &MutationConfig,
+        recurrent: bool,
+    ) {
+        const MAX_ATTEMPTS: usize = 20;
+
+        // Output nodes can only feed back into the network in recurrent mode
+        let in_nodes: Vec<usize> = individual
+            .nodes
+            .iter()
+            .filter(|(_, node)| recurrent || !node.is_output_node())
+            .map(|(id, _)| *id)
+            .collect();
+        let out_nodes: Vec<usize> = individual
+            .nodes
+            .iter()
+            .filter(|(_, node)| !node.is_input_node())
+            .map(|(id, _)| *id)
+            .collect();
+        for _ in 0..MAX_ATTEMPTS {
+            let in_node = *in_nodes.choose(rng).unwrap();
+            let out_node = *out_nodes.choose(rng).unwrap();
+            // Prevent duplicate connections, and cycles unless in recurrent
+            // mode
+            if individual.has_connection(in_node, out_node)
+                || (!recurrent && individual.has_path(out_node, in_node))
+            {
+                continue;
+            }
+            individual.genes.push(Gene {
+                in_node,
+                out_node,
+                weight: mutation.random_weight(rng),
+                enabled: true,
+                innovation_number: innovations.connection(in_node, out_node),
+            });
+            return;
         }
-        let innovation_number = max_innovation_number + 1;
-        individual.genes.push(Gene {
-            in_node,
-            out_node,
-            weight: 1.0,
-            enabled: true,
-            innovation_number,
-        });
-        innovation_number
     }
 
-    fn mutate_add_node(individual: &mut Individual, max_innovation_number: u64) -> u64 {
+    fn mutate_add_node(
+        individual: &mut Individual,
+        innovations: &mut InnovationRegistry,
+        rng: &mut Pcg64,
+    ) {
         let gene = {
-            let mut rng = rand::thread_rng();
             let mut enabled_genes = individual.enabled_genes_mut();
-            let gene: &mut Gene = enabled_genes.choose_mut(&mut rng).unwrap();
+            let gene: &mut Gene = match enabled_genes.choose_mut(rng) {
+                Some(gene) => gene,
+                None => return,
+            };
             gene.enabled = false;
             gene.clone()
         };
 
-        let new_node = Node(NodeType::Hidden);
-        individual.nodes.push(new_node);
-        let new_node_index = individual.nodes.len() - 1;
+        let split =
+            innovations.split_connection(gene.innovation_number, gene.in_node, gene.out_node);
+        individual
+            .nodes
+            .insert(split.node, Node::new(NodeType::Hidden));
 
         individual.genes.push(Gene {
             in_node: gene.in_node,
-            out_node: new_node_index,
+            out_node: split.node,
             weight: 1.0,
             enabled: true,
-            innovation_number: max_innovation_number + 1,
+            innovation_number: split.in_innovation_number,
         });
         individual.genes.push(Gene {
-            in_node: new_node_index,
+            in_node: split.node,
             out_node: gene.out_node,
             weight: 1.0,
             enabled: true,
-            innovation_number: max_innovation_number + 2,
+            innovation_number: split.out_innovation_number,
         });
+    }
 
-        max_innovation_number + 2
+    fn mutate_weights(individual: &mut Individual, rng: &mut Pcg64, mutation: &MutationConfig) {
+        let perturbation = Normal::new(0.0, mutation.weight_perturbation_std_dev).unwrap();
+        for gene in &mut individual.genes {
+            if rng.gen::<f64>() < mutation.weight_replacement_rate {
+                gene.weight = mutation.random_weight(rng);
+            } else if rng.gen::<f64>() < mutation.weight_perturbation_rate {
+                gene.weight += perturbation.sample(rng);
+            }
+            gene.weight = mutation.clamp_weight(gene.weight);
+        }
     }
 
-    fn mutate_change_weight(individual: &mut Individual, max_innovation_number: u64) -> u64 {
-        let mut rng = rand::thread_rng();
-        let mut enabled_genes = individual.enabled_genes_mut();
-        let gene: &mut Gene = enabled_genes.choose_mut(&mut rng).unwrap();
-        gene.weight = rng.gen_range(-2.0, 2.0);
-        max_innovation_number
+    // Genes are never re-enabled into a cycle, as cycles are checked against
+    // disabled genes too when connections are added
+    fn mutate_toggle_gene(individual: &mut Individual, rng: &mut Pcg64) {
+        if let Some(gene) = individual.genes.choose_mut(rng) {
+            gene.enabled = !gene.enabled;
+        }
     }
 
-    fn mutate(mut individual: &mut Individual, max_innovation_number: u64) -> u64 {
-        let mut rng = rand::thread_rng();
-        let f = match rng.gen_range(0, 3) {
-            0 => Self::mutate_add_connection,
-            1 => Self::mutate_add_node,
-            _ => Self::mutate_change_weight,
-        };
-        f(&mut individual, max_innovation_number)
+    fn mutate_delete_connection(individual: &mut Individual, rng: &mut Pcg64) {
+        if individual.genes.is_empty() {
+            return;
+        }
+        let index = rng.gen_range(0, individual.genes.len());
+        individual.genes.remove(index);
+    }
+
+    fn mutate_prune_node(individual: &mut Individual, rng: &mut Pcg64) {
+        let hidden_nodes: Vec<usize> = individual
+            .nodes
+            .iter()
+            .filter(|(_, node)| !node.is_input_node() && !node.is_output_node())
+            .map(|(id, _)| *id)
+            .collect();
+        if let Some(node) = hidden_nodes.choose(rng) {
+            individual.nodes.remove(node);
+            individual
+                .genes
+                .retain(|gene| gene.in_node != *node && gene.out_node != *node);
+        }
+    }
+
+    fn mutate_change_activation(individual: &mut Individual, rng: &mut Pcg64) {
+        let mut nodes: Vec<&mut Node> = individual
+            .nodes
+            .values_mut()
+            .filter(|node| !node.is_input_node())
+            .collect();
+        if let Some(node) = nodes.choose_mut(rng) {
+            node.activation = *Activation::ALL.choose(rng).unwrap();
+        }
+    }
+
+    fn mutate(
+        individual: &mut Individual,
+        innovations: &mut InnovationRegistry,
+        rng: &mut Pcg64,
+        mutation: &MutationConfig,
+        recurrent: bool,
+    ) {
+        Self::mutate_weights(individual, rng, mutation);
+        if rng.gen::<f64>() < mutation.add_connection_rate {
+            Self::mutate_add_connection(individual, innovations, rng, mutation, recurrent);
+        }
+        if rng.gen::<f64>() < mutation.add_node_rate {
+            Self::mutate_add_node(individual, innovations, rng);
+        }
+        if rng.gen::<f64>() < mutation.toggle_gene_rate {
+            Self::mutate_toggle_gene(individual, rng);
+        }
+        if rng.gen::<f64>() < mutation.delete_connection_rate {
+            Self::mutate_delete_connection(individual, rng);
+        }
+        if rng.gen::<f64>() < mutation.prune_node_rate {
+            Self::mutate_prune_node(individual, rng);
+        }
+        if rng.gen::<f64>() < mutation.change_activation_rate {
+            Self::mutate_change_activation(individual, rng);
+        }
     }
 
-    fn compatibility_distance(a: &Individual, b: &Individual) -> f64 {
+    fn compatibility_distance(a: &Individual, b: &Individual, evolution: &EvolutionConfig) -> f64 {
         let n = a.genes.len().max(b.genes.len());
         let n = if n < 20 { 1.0 } else { n as f64 };
-        let a_innos: HashSet<_> = a.genes.iter().map(|g| g.innovation_number).collect();
-        let b_innos: HashSet<_> = b.genes.iter().map(|g| g.innovation_number).collect();
-        let disjoint_size = a_innos
-            .symmetric_difference(&b_innos)
-            .collect::<HashSet<_>>()
-            .len() as f64;
-        let mut weights: HashMap<_, _> = a_innos
-            .intersection(&b_innos)
-            .cloned()
-            .map(|i| (i, (0.0, 0.0)))
+        let b_weights: HashMap<_, _> = b
+            .genes
+            .iter()
+            .map(|gene| (gene.innovation_number, gene.weight))
             .collect();
+        // Sum weight differences in A's gene order, as floating point addition
+        // is not associative and distances must not depend on hash ordering
+        let mut matching = 0;
+        let mut sum_of_differences = 0.0;
         for gene in &a.genes {
-            weights
-                .entry(gene.innovation_number)
-                .and_modify(|(w, _)| *w = gene.weight);
-        }
-        for gene in &b.genes {
-            weights
-                .entry(gene.innovation_number)
-                .and_modify(|(_, w)| *w = gene.weight);
+            if let Some(weight_b) = b_weights.get(&gene.innovation_number) {
+                matching += 1;
+                sum_of_differences += (gene.weight - weight_b).abs();
+            }
         }
-        let sum_of_differences = weights.values().fold(0.0, |sum, (weight_a, weight_b)| {
-            sum + (weight_a - weight_b).abs()
-        });
-        let average_weights_difference = sum_of_differences / weights.len() as f64;
-        (DISJOINT_COEFFICIENT * disjoint_size / n)
-            + (WEIGHTS_COEFFICIENT * average_weights_difference)
+        let disjoint_size = (a.genes.len() + b.genes.len() - 2 * matching) as f64;
+        // Genomes without any gene in common only differ by their disjoint
+        // genes
+        let average_weights_difference = if matching == 0 {
+            0.0
+        } else {
+            sum_of_differences / matching as f64
+        };
+        (evolution.disjoint_coefficient * disjoint_size / n)
+            + (evolution.weights_coefficient * average_weights_difference)
     }
 
-    fn is_same_species(a: &Individual, b: &Individual) -> bool {
-        Self::compatibility_distance(a, b) < COMPATIBILITY_THRESHOLD
+    fn is_same_species(a: &Individual, b: &Individual, evolution: &EvolutionConfig) -> bool {
+        Self::compatibility_distance(a, b, evolution) < evolution.compatibility_threshold
     }
 
-    pub fn load_snapshot(&mut self, filename: &str) {
-        use std::fs::File;
-        use std::io::BufReader;
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-        let snapshot: AiSnapshot = serde_json::from_reader(reader).unwrap();
-        self.pool = snapshot.pool;
-        self.generation = snapshot.generation;
-        self.next_generation();
+    // An AI holding the population of a snapshot, as it was when the
+    // snapshot was taken. Its random number generator is restored from the
+    // snapshot too, so the seed in `options` is unused
+    pub fn from_snapshot(options: AiOptions, path: &str) -> Result<Self, SnapshotError> {
+        let snapshot = snapshot::read(Path::new(path))?;
+        let mut ai = Self::with_options(options, snapshot.options.seed.unwrap_or(0));
+        ai.pool = snapshot.pool;
+        ai.generation = snapshot.generation;
+        ai.max_fitness = snapshot.max_fitness;
+        ai.rng = snapshot.rng;
+        ai.innovations = snapshot.innovations;
+        // The input and output layers are part of the networks' structure, so
+        // the game, inputs, buttons, view and observation they were trained
+        // with take precedence over the configured ones. So does whether they
+        // are recurrent, which decides how their cycles are evaluated
+        if snapshot.options.inputs != ai.inputs {
+            println!(
+                "Using the inputs from the snapshot: {:?}",
+                snapshot.options.inputs
+            );
+        }
+        ai.inputs = snapshot.options.inputs;
+        if snapshot.options.outputs != ai.outputs {
+            println!(
+                "Using the output buttons from the snapshot: {:?}",
+                snapshot.options.outputs
+            );
+        }
+        ai.outputs = snapshot.options.outputs;
+        if snapshot.options.view != ai.individual_state_options.view {
+            println!(
+                "Using the view from the snapshot: {:?}",
+                snapshot.options.view
+            );
+        }
+        ai.individual_state_options.view = snapshot.options.view;
+        if snapshot.options.observation != ai.individual_state_options.observation {
+            println!(
+                "Using the observation from the snapshot: {:?}",
+                snapshot.options.observation
+            );
+        }
+        ai.individual_state_options.observation = snapshot.options.observation;
+        if snapshot.options.game != ai.individual_state_options.game {
+            println!(
+                "Using the game from the snapshot: {:?}",
+                snapshot.options.game
+            );
+        }
+        ai.individual_state_options.game = snapshot.options.game;
+        if snapshot.options.recurrent != ai.individual_state_options.recurrent {
+            println!(
+                "Using the recurrent setting from the snapshot: {}",
+                snapshot.options.recurrent
+            );
+        }
+        ai.individual_state_options.recurrent = snapshot.options.recurrent;
+        ai.current_individual_state = IndividualState::new(ai.individual_state_options, 0);
+        // Only replace the run's champion with a fitter individual
+        if let Ok(champion) = snapshot::read_genome(&ai.champion_path()) {
+            ai.champion_fitness = champion.fitness;
+        }
+        Ok(ai)
+    }
+
+    // Continues the run that took the snapshot, starting with the generation
+    // bred from the snapshot's population
+    pub fn resume(options: AiOptions, path: &str) -> Result<Self, SnapshotError> {
+        let mut ai = Self::from_snapshot(options, path)?;
+        ai.next_generation();
+        println!("Resuming from generation {}", ai.generation);
+        Ok(ai)
     }
 
-    fn save_snapshot(&self) {
-        use std::fs::File;
-        let snapshot = AiSnapshot {
+    fn save_snapshot(&self) -> Result<(), SnapshotError> {
+        let snapshot = snapshot::AiSnapshot {
+            version: snapshot::SNAPSHOT_VERSION,
+            generation: self.generation,
+            max_fitness: self.max_fitness,
             pool: self.pool.clone(),
+            rng: self.rng.clone(),
+            innovations: self.innovations.clone(),
+            options: self.options(),
+        };
+        let path = self
+            .snapshot_directory
+            .join(format!("g-{}.json", self.generation));
+        snapshot::write(&path, &snapshot)
+    }
+
+    fn champion_path(&self) -> PathBuf {
+        self.snapshot_directory.join("champion.json")
+    }
+
+    fn save_genome(&self, individual: &Individual, path: &Path) -> Result<(), SnapshotError> {
+        let genome = snapshot::Genome {
+            version: snapshot::GENOME_VERSION,
             generation: self.generation,
+            fitness: individual.fitness,
+            individual: individual.clone(),
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            recurrent: self.individual_state_options.recurrent,
+            view: self.individual_state_options.view,
+            observation: self.individual_state_options.observation,
+            game: self.individual_state_options.game,
+        };
+        snapshot::write(path, &genome)
+    }
+
+    // Exports the fittest individual evaluated so far in this generation as a
+    // genome file, if it beats every champion exported before it
+    fn update_champion(&mut self) {
+        let champion = self
+            .pool
+            .iter()
+            .flat_map(|species| species.members.iter())
+            .max_by_key(|individual| individual.fitness);
+        let champion = match champion {
+            Some(champion) if champion.fitness > self.champion_fitness => champion,
+            _ => return,
         };
-        let filename = format!("snapshots/g-{}.json", self.generation);
-        serde_json::to_writer(&File::create(filename).unwrap(), &snapshot).unwrap();
+        let path = self.champion_path();
+        match self.save_genome(champion, &path) {
+            Ok(()) => {
+                println!(
+                    "Saved new champion (fitness = {}) to {}",
+                    champion.fitness,
+                    path.display()
+                );
+                self.champion_fitness = champion.fitness;
+            }
+            Err(err) => eprintln!("Couldn't save the champion: {}", err),
+        }
+    }
+
+    // Ends the level the current individual is playing, and resets the
+    // emulator to the next level to play: the individual's next level, or the
+    // first level for the next individual. Returns true, without resetting the
+    // emulator, if the individual has just succeeded on every level
+    pub fn finish_level(&mut self, emulator: &mut Emulator) -> bool {
+        self.current_level_fitnesses.push(
+            self.current_individual_state
+                .fitness(&*self.fitness_function),
+        );
+        self.current_levels_succeeded =
+            self.current_levels_succeeded && self.current_individual_state.has_succeeded();
+        if self.current_level + 1 < emulator.levels() {
+            self.current_level += 1;
+            self.current_individual_state =
+                IndividualState::new(self.individual_state_options, self.current_level);
+            emulator.reset(self.current_level);
+            return false;
+        }
+
+        let level_fitnesses = std::mem::take(&mut self.current_level_fitnesses);
+        let succeeded = self.current_levels_succeeded;
+        if emulator.levels() > 1 {
+            println!("Level fitnesses: {:?}", level_fitnesses);
+        }
+        let (species_index, individual_index) = self.current_individual;
+        let individual = &mut self.pool[species_index].members[individual_index];
+        individual.fitness = self.fitness_aggregation.aggregate(&level_fitnesses);
+        individual.level_fitnesses = level_fitnesses;
+        self.current_level = 0;
+        self.current_levels_succeeded = true;
+        self.current_individual_state = IndividualState::new(self.individual_state_options, 0);
+        if succeeded {
+            self.update_champion();
+            return true;
+        }
+        self.next_individual();
+        emulator.reset(0);
+        false
+    }
+
+    // Replaces the fitness function selected in the options, e.g. with one
+    // that isn't built in. It is not saved in snapshots, so it needs setting
+    // again after resuming
+    pub fn set_fitness_function(
+        &mut self,
+        fitness_function: Arc<dyn FitnessFunction + Send + Sync>,
+    ) {
+        self.fitness_function = fitness_function;
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn current_level(&self) -> usize {
+        self.current_level
+    }
+
+    // Best fitness on each level during the last generation
+    pub fn best_level_fitnesses(&self) -> &[u64] {
+        &self.best_level_fitnesses
+    }
+
+    // Records the best fitness on each level, and logs it along with the mean
+    // fitness when there are several levels
+    fn update_level_fitnesses(&mut self) {
+        let individuals: Vec<&Individual> = self
+            .pool
+            .iter()
+            .flat_map(|species| species.members.iter())
+            .collect();
+        let levels = individuals
+            .iter()
+            .map(|individual| individual.level_fitnesses.len())
+            .max()
+            .unwrap_or(0);
+        self.best_level_fitnesses = (0..levels)
+            .map(|level| {
+                let fitnesses = individuals
+                    .iter()
+                    .filter_map(|individual| individual.level_fitnesses.get(level));
+                let best = fitnesses.clone().max().cloned().unwrap_or(0);
+                let mean = fitnesses.clone().sum::<u64>() as f64 / fitnesses.count().max(1) as f64;
+                if levels > 1 {
+                    println!(
+                        "Level {}: best fitness = {}, mean = {:.1}",
+                        level, best, mean
+                    );
+                }
+                best
+            })
+            .collect();
     }
 
     fn update_max_fitness(&mut self) {
@@ -554,43 +1456,63 @@ impl Ai {
             }
         }
         let max_fitness = self.max_fitness;
+        let max_staleness = self.evolution.max_species_staleness;
         self.pool.retain(|species| {
-            species.staleness < MAX_SPECIES_STALENESS || species.top_fitness == max_fitness
+            species.staleness < max_staleness || species.top_fitness == max_fitness
         });
     }
 
     fn remove_weak_species(&mut self) {
-        if self.pool.len() > MAX_SPECIES {
+        if self.pool.len() > self.evolution.max_species {
             self.pool
                 .sort_by_key(|species| Reverse(species.members[0].fitness));
-            self.pool.truncate(MAX_SPECIES / 2);
+            self.pool.truncate(self.evolution.max_species / 2);
         }
     }
 
-    fn population(&self) -> i64 {
-        self.pool.iter().map(|species| species.len()).sum::<usize>() as i64
+    fn population(&self) -> usize {
+        self.pool.iter().map(|species| species.len()).sum::<usize>()
     }
 
-    // Breed individuals of same species
-    fn cross_over_within_species(&mut self) {
-        let mut rng = rand::thread_rng();
-        for species in &mut self.pool {
-            if species.len() < 2 {
-                continue;
-            }
-            for _ in 0..species.len() {
-                let parents: Vec<&Individual> =
-                    species.members.choose_multiple(&mut rng, 2).collect();
-                let child = Self::cross_over(parents[0], parents[1]);
-                species.members.push(child);
-            }
+    // Number of children each species gets in the next generation, in
+    // proportion to its adjusted fitness. Fractional shares are rounded using
+    // the largest remainder method so that the quotas always add up to
+    // exactly the population size
+    fn offspring_quotas(&self) -> Vec<usize> {
+        let population = self.evolution.population;
+        let adjusted_fitnesses: Vec<f64> = self
+            .pool
+            .iter()
+            .map(|species| species.adjusted_fitness())
+            .collect();
+        let total: f64 = adjusted_fitnesses.iter().sum();
+        let shares: Vec<f64> = adjusted_fitnesses
+            .iter()
+            .map(|adjusted_fitness| {
+                if total > 0.0 {
+                    adjusted_fitness / total * population as f64
+                } else {
+                    population as f64 / self.pool.len() as f64
+                }
+            })
+            .collect();
+        let mut quotas: Vec<usize> = shares.iter().map(|share| share.floor() as usize).collect();
+        let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+        by_remainder.sort_by(|a, b| {
+            let remainder = |i: usize| shares[i] - shares[i].floor();
+            remainder(*b).partial_cmp(&remainder(*a)).unwrap()
+        });
+        let missing = population - quotas.iter().sum::<usize>();
+        for i in by_remainder.into_iter().take(missing) {
+            quotas[i] += 1;
         }
+        quotas
     }
 
     fn add_to_pool(&mut self, individual: Individual) {
         let mut species: Option<&mut Species> = None;
         for s in &mut self.pool {
-            if Self::is_same_species(&individual, &s.members[0]) {
+            if Self::is_same_species(&individual, &s.members[0], &self.evolution) {
                 species = Some(s);
                 break;
             }
@@ -598,8 +1520,9 @@ impl Ai {
         match species {
             Some(s) => s.members.push(individual),
             None => {
+                let id = self.pool.iter().map(|species| species.id + 1).max();
                 let new_species = Species {
-                    id: self.pool.iter().map(|species| species.id).max().unwrap() + 1,
+                    id: id.unwrap_or(0),
                     members: vec![individual],
                     ..Species::default()
                 };
@@ -608,74 +1531,190 @@ impl Ai {
         }
     }
 
-    // Breed individuals of different species
-    fn cross_over_between_species(&mut self) {
-        let mut rng = rand::thread_rng();
-        let children_needed = DESIRED_POPULATION - self.population();
-        let children_needed = if children_needed > 150 {
-            children_needed / 4
+    // Produces a child of a (culled) species. The second parent usually comes
+    // from the same species, and occasionally from another one
+    fn breed(
+        species: &Species,
+        pool: &[Species],
+        innovations: &mut InnovationRegistry,
+        rng: &mut Pcg64,
+        evolution: &EvolutionConfig,
+        mutation: &MutationConfig,
+        recurrent: bool,
+    ) -> Individual {
+        let parents: Vec<&Individual> = species.members.choose_multiple(rng, 2).collect();
+        let parent_a = parents[0];
+        let parent_b = if rng.gen::<f64>() < evolution.interspecies_crossover_probability {
+            pool.choose(rng).unwrap().members.choose(rng)
         } else {
-            children_needed
+            parents.get(1).cloned()
         };
-        for _ in 0..rng.gen_range(0, children_needed) {
-            let species: Vec<&Species> = self.pool.choose_multiple(&mut rng, 2).collect();
-            let parent_a = species[0].members.choose(&mut rng).unwrap();
-            let parent_b = species[1].members.choose(&mut rng).unwrap();
-            let child = Self::cross_over(parent_a, parent_b);
-            self.add_to_pool(child);
+        let mut child = match parent_b {
+            Some(parent_b) => Self::cross_over(parent_a, parent_b, rng),
+            None => Individual {
+                fitness: 0,
+                level_fitnesses: vec![],
+                ..parent_a.clone()
+            },
+        };
+        if parent_b.is_none() || rng.gen::<f64>() < evolution.crossover_mutation_probability {
+            Self::mutate(&mut child, innovations, rng, mutation, recurrent);
         }
+        child
     }
 
-    fn mutate_random_invididuals(&mut self) {
-        let mut max_innovation_number = self
-            .pool
-            .iter()
-            .map(|species| &species.members)
-            .flatten()
-            .map(|individual| &individual.genes)
-            .flatten()
-            .map(|gene| gene.innovation_number)
-            .max()
-            .unwrap();
-        for species in &mut self.pool {
-            for mut individual in &mut species.members {
-                if rand::random::<f64>() < MUTATION_PROBABILITY {
-                    max_innovation_number = Self::mutate(&mut individual, max_innovation_number);
-                }
+    // Replaces the population with the children of the current species. Each
+    // species keeps its champion and fills the rest of its quota with
+    // offspring, which are then speciated again
+    fn reproduce(&mut self, quotas: Vec<usize>) {
+        self.innovations.start_generation();
+        let parents = std::mem::take(&mut self.pool);
+        let evolution = self.evolution;
+        let mutation = self.mutation;
+        let recurrent = self.individual_state_options.recurrent;
+        let mut children = vec![];
+        for (species, quota) in parents.iter().zip(quotas) {
+            if quota == 0 {
+                continue;
+            }
+            self.pool.push(Species {
+                id: species.id,
+                members: vec![species.members[0].clone()],
+                staleness: species.staleness,
+                top_fitness: species.top_fitness,
+            });
+            for _ in 1..quota {
+                let child = Self::breed(
+                    species,
+                    &parents,
+                    &mut self.innovations,
+                    &mut self.rng,
+                    &evolution,
+                    &mutation,
+                    recurrent,
+                );
+                children.push(child);
             }
         }
+        for child in children {
+            self.add_to_pool(child);
+        }
     }
 
     fn next_generation(&mut self) {
         self.update_max_fitness();
         self.sort_species();
-        self.cull_species();
         self.remove_stale_species();
         self.remove_weak_species();
-        self.cross_over_within_species();
-        self.cross_over_between_species();
-        self.mutate_random_invididuals();
+        let quotas = self.offspring_quotas();
+        self.cull_species();
+        self.reproduce(quotas);
         self.generation += 1;
     }
 
-    pub fn next_individual(&mut self) {
-        let (species_index, individual_index) = self.current_individual;
-        let individual = &mut self.pool[species_index].members[individual_index];
-        individual.fitness = self.current_individual_state.fitness();
+    fn finish_generation(&mut self) {
+        self.update_level_fitnesses();
+        self.update_champion();
+        // Losing a snapshot is not worth stopping the run for
+        if let Err(err) = self.save_snapshot() {
+            eprintln!("Couldn't save generation {}: {}", self.generation, err);
+        }
+        self.next_generation();
+        println!(
+            "New generation (g = {}). Population = {}. Species = {}",
+            self.generation,
+            self.population(),
+            self.pool.len()
+        );
+    }
+
+    // Evaluates every individual of the current generation, spreading them
+    // over the given emulators (one worker thread per emulator), then breeds
+    // the next generation. `on_frame` is called by each worker after every
+    // frame with the worker's index and screen, and returns false to
+    // interrupt the evaluation.
+    pub fn evaluate_generation(
+        &mut self,
+        emulators: &mut [Emulator],
+        on_frame: &(dyn Fn(usize, Screen) -> bool + Sync),
+    ) -> GenerationResult {
+        let options = self.individual_state_options;
+        let inputs = &self.inputs;
+        let outputs = &self.outputs;
+        let fitness_function = &*self.fitness_function;
+        let (job_sender, job_receiver) = unbounded();
+        for (species_index, species) in self.pool.iter().enumerate() {
+            for (individual_index, individual) in species.members.iter().enumerate() {
+                job_sender
+                    .send(((species_index, individual_index), individual))
+                    .unwrap();
+            }
+        }
+        drop(job_sender);
+        let (result_sender, result_receiver) = unbounded();
+
+        crossbeam::scope(|scope| {
+            for (worker, emulator) in emulators.iter_mut().enumerate() {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                scope.spawn(move |_| {
+                    let on_frame = |screen| on_frame(worker, screen);
+                    for (index, individual) in job_receiver.iter() {
+                        let states =
+                            individual.play_levels(emulator, options, inputs, outputs, &on_frame);
+                        match states {
+                            Some(states) => {
+                                let level_fitnesses: Vec<u64> = states
+                                    .iter()
+                                    .map(|state| state.fitness(fitness_function))
+                                    .collect();
+                                let has_succeeded =
+                                    states.iter().all(|state| state.has_succeeded());
+                                result_sender
+                                    .send((index, level_fitnesses, has_succeeded))
+                                    .unwrap()
+                            }
+                            None => return,
+                        }
+                    }
+                });
+            }
+        })
+        .unwrap();
+        drop(job_receiver);
+        drop(result_sender);
+
+        let mut evaluated = 0;
+        let mut succeeded = false;
+        for ((species_index, individual_index), level_fitnesses, has_succeeded) in
+            result_receiver.iter()
+        {
+            let individual = &mut self.pool[species_index].members[individual_index];
+            individual.fitness = self.fitness_aggregation.aggregate(&level_fitnesses);
+            individual.level_fitnesses = level_fitnesses;
+            succeeded = succeeded || has_succeeded;
+            evaluated += 1;
+        }
+
+        if succeeded {
+            self.update_champion();
+            GenerationResult::Succeeded
+        } else if evaluated < self.population() {
+            GenerationResult::Interrupted
+        } else {
+            self.finish_generation();
+            GenerationResult::Completed
+        }
+    }
 
+    fn next_individual(&mut self) {
+        let (species_index, individual_index) = self.current_individual;
         let pool_size = self.pool.len();
         let species_size = self.pool[species_index].len();
 
         self.current_individual = if individual_index == species_size - 1 {
             if species_index == pool_size - 1 {
-                self.save_snapshot();
-                self.next_generation();
-                println!(
-                    "New generation (g = {}). Population = {}. Species = {}",
-                    self.generation,
-                    self.population(),
-                    self.pool.len()
-                );
+                self.finish_generation();
                 (0, 0)
             } else {
                 (species_index + 1, 0)
@@ -684,10 +1723,90 @@ impl Ai {
             (species_index, individual_index + 1)
         };
 
-        self.current_individual_state = IndividualState::new(IndividualStateOptions {
-            stuck_timeout_ms: self.stuck_timeout_ms,
-            finish_timeout_ms: self.finish_timeout_ms,
-        });
+        self.current_individual_state =
+            IndividualState::new(self.individual_state_options, self.current_level);
+    }
+
+    // A controller playing the given individual
+    pub fn controller(
+        &self,
+        species_index: usize,
+        individual_index: usize,
+    ) -> Result<Controller, String> {
+        let species = self.pool.get(species_index).ok_or(format!(
+            "There is no species {} (the pool has {} species)",
+            species_index,
+            self.pool.len()
+        ))?;
+        let individual = species.members.get(individual_index).ok_or(format!(
+            "There is no individual {} in species {} (it has {} members)",
+            individual_index,
+            species_index,
+            species.len()
+        ))?;
+        Ok(Controller::new(
+            individual.clone(),
+            self.inputs.clone(),
+            self.outputs.clone(),
+            self.individual_state_options,
+            self.fitness_function.clone(),
+        ))
+    }
+
+    pub fn print_statistics(&self) {
+        let fitnesses: Vec<u64> = self
+            .pool
+            .iter()
+            .flat_map(|species| species.members.iter().map(|individual| individual.fitness))
+            .collect();
+        let average = |total: f64, count: usize| total / count.max(1) as f64;
+        println!(
+            "Generation {}. Population = {}. Species = {}",
+            self.generation,
+            self.population(),
+            self.pool.len()
+        );
+        println!(
+            "Fitness: max = {}, average = {:.1}",
+            fitnesses.iter().max().unwrap_or(&0),
+            average(fitnesses.iter().sum::<u64>() as f64, fitnesses.len())
+        );
+        println!("Inputs: {:?}", self.inputs);
+        println!("Outputs: {:?}", self.outputs);
+        for (index, species) in self.pool.iter().enumerate() {
+            let members = &species.members;
+            let max_fitness = members.iter().map(|individual| individual.fitness).max();
+            let genes: usize = members
+                .iter()
+                .map(|individual| individual.genes.len())
+                .sum();
+            let hidden_nodes: usize = members
+                .iter()
+                .map(|individual| {
+                    individual
+                        .nodes
+                        .values()
+                        .filter(|node| !node.is_input_node() && !node.is_output_node())
+                        .count()
+                })
+                .sum();
+            println!(
+                "Species {} (id {}): members = {}, max fitness = {}, staleness = {}, \
+                 average genes = {:.1}, average hidden nodes = {:.1}",
+                index,
+                species.id,
+                members.len(),
+                max_fitness.unwrap_or(0),
+                species.staleness,
+                average(genes as f64, members.len()),
+                average(hidden_nodes as f64, members.len())
+            );
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.max_generations
+            .is_some_and(|max_generations| self.generation >= max_generations)
     }
 
     pub fn update_game_state(&mut self, mut cpu: &mut cpu::Cpu<mem::MemMap>) {
@@ -714,26 +1833,72 @@ impl Ai {
         self.current_individual_state.get_screen()
     }
 
-    pub fn get_inputs(&self) -> Inputs {
-        const THRESHOLD: f64 = 0.5;
-
+    pub fn get_inputs(&mut self) -> Inputs {
         let (species_index, individual_index) = self.current_individual;
         let individual = &self.pool[species_index].members[individual_index];
-        let input = {
-            let screen = self.get_screen();
-            let mut input = [0.0; SCREEN_SIZE * SCREEN_SIZE];
-            for i in 0..SCREEN_SIZE {
-                for j in 0..SCREEN_SIZE {
-                    input[(i * SCREEN_SIZE) + j] = screen[i][j].as_nn_input();
-                }
-            }
-            input
-        };
-        let (right_value, a_value) = individual.evaluate(input);
+        individual.get_inputs(
+            &mut self.current_individual_state,
+            &self.inputs,
+            &self.outputs,
+        )
+    }
+}
 
-        Inputs {
-            right: right_value > THRESHOLD,
-            a: a_value > THRESHOLD,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn individual(innovation_numbers: &[u64]) -> Individual {
+        Individual {
+            genes: innovation_numbers
+                .iter()
+                .map(|&innovation_number| Gene {
+                    in_node: 0,
+                    out_node: 1,
+                    weight: 1.0,
+                    enabled: true,
+                    innovation_number,
+                })
+                .collect(),
+            ..Individual::default()
         }
     }
+
+    #[test]
+    fn compatibility_distance_without_matching_genes() {
+        let evolution = EvolutionConfig::default();
+        let a = individual(&[0]);
+        let b = individual(&[1, 2]);
+        let distance = Ai::compatibility_distance(&a, &b, &evolution);
+        assert_eq!(distance, evolution.disjoint_coefficient * 3.0);
+        assert!(Ai::is_same_species(&a, &b, &evolution));
+    }
+
+    #[test]
+    fn inspect_and_replay_individual_from_snapshot() {
+        let directory = std::env::temp_dir().join(format!(
+            "mario-neural-network-inspect-{}",
+            std::process::id()
+        ));
+        let options = AiOptions {
+            seed: Some(0),
+            evolution: EvolutionConfig {
+                population: 10,
+                ..EvolutionConfig::default()
+            },
+            snapshot_directory: directory.to_string_lossy().into_owned(),
+            ..AiOptions::default()
+        };
+        Ai::new(options.clone()).save_snapshot().ok().unwrap();
+        let path = directory.join("g-0.json");
+        let ai = Ai::from_snapshot(options, &path.to_string_lossy());
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let ai = ai.ok().unwrap();
+        ai.print_statistics();
+        assert_eq!(ai.population(), 10);
+        assert!(ai.controller(0, 0).is_ok());
+        assert!(ai.controller(ai.pool.len(), 0).is_err());
+        assert!(ai.controller(0, ai.pool[0].len()).is_err());
+    }
 }
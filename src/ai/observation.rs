@@ -0,0 +1,81 @@
+use crate::nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::utils::View;
+
+use serde::{Deserialize, Serialize};
+
+// What the network sees of the game around Mario. Either way it sees one
+// value per cell of the view
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum Observation {
+    // Tiles decoded from the level and enemy data in the game's RAM
+    #[default]
+    Tiles,
+    // The frame drawn by the PPU, split into cells of `cell_size` by
+    // `cell_size` pixels. The crop follows the player, who is in the centre
+    // cell, rather than the scroll position, so it only relies on the game
+    // adapter for the player's position on the screen
+    Pixels {
+        cell_size: usize,
+        colours: PixelColours,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum PixelColours {
+    // Mean brightness of the cell, from 0 (black) to 1 (white)
+    Greyscale,
+    // Mean brightness rounded to one of `levels` evenly spaced values, so
+    // that small differences in the background don't reach the network. The
+    // colours themselves aren't quantised. Configs and snapshots written
+    // before the rename call this `Quantised`
+    #[serde(alias = "Quantised")]
+    BrightnessLevels {
+        levels: u8,
+    },
+}
+
+// Perceived brightness of a pixel of the PPU's RGB frame, from 0 to 1
+fn brightness(frame: &[u8], x: usize, y: usize) -> f64 {
+    let offset = (y * SCREEN_WIDTH + x) * 3;
+    let (r, g, b) = (frame[offset], frame[offset + 1], frame[offset + 2]);
+    (0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b)) / 255.0
+}
+
+// One value per cell of the view, row by row. Pixels off the frame are black
+pub fn pixels(
+    frame: &[u8],
+    player_position: (i32, i32),
+    view: View,
+    cell_size: usize,
+    colours: PixelColours,
+) -> Vec<f64> {
+    let cell_size = cell_size as i32;
+    let (player_x, player_y) = player_position;
+    let mut values = Vec::with_capacity(view.tiles());
+    for i in -(view.above as i32)..=view.below as i32 {
+        for j in -(view.behind as i32)..=view.ahead as i32 {
+            let left = player_x + j * cell_size;
+            let top = player_y + i * cell_size;
+            let mut total = 0.0;
+            for y in top..top + cell_size {
+                for x in left..left + cell_size {
+                    let on_frame =
+                        0 <= x && x < SCREEN_WIDTH as i32 && 0 <= y && y < SCREEN_HEIGHT as i32;
+                    if on_frame {
+                        total += brightness(frame, x as usize, y as usize);
+                    }
+                }
+            }
+            let mean = total / f64::from(cell_size * cell_size).max(1.0);
+            let value = match colours {
+                PixelColours::Greyscale => mean,
+                PixelColours::BrightnessLevels { levels } => {
+                    let steps = f64::from(levels.max(2) - 1);
+                    (mean * steps).round() / steps
+                }
+            };
+            values.push(value);
+        }
+    }
+    values
+}
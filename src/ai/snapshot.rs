@@ -0,0 +1,422 @@
+use super::innovation::InnovationRegistry;
+use super::{AiOptions, Game, Gene, Individual, Node, NodeType, Observation, ScalarInput, Species};
+use crate::nes::input::Button;
+use crate::utils::View;
+
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Version of the snapshots written by this build. Version 0 is the original,
+// unversioned format, which only held the pool and the generation
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+// Version of the genome files written by this build
+pub const GENOME_VERSION: u64 = 1;
+
+// Everything needed to continue a run from the end of a generation
+#[derive(Serialize, Deserialize)]
+pub(super) struct AiSnapshot {
+    pub(super) version: u64,
+    pub(super) generation: u64,
+    pub(super) max_fitness: u64,
+    pub(super) pool: Vec<Species>,
+    pub(super) rng: Pcg64,
+    pub(super) innovations: InnovationRegistry,
+    // Options of the run that took the snapshot
+    pub(super) options: AiOptions,
+}
+
+// A single network, along with the inputs and outputs it was trained with,
+// that can be played without the rest of its population
+#[derive(Serialize, Deserialize)]
+pub(super) struct Genome {
+    pub(super) version: u64,
+    pub(super) generation: u64,
+    pub(super) fitness: u64,
+    pub(super) individual: Individual,
+    pub(super) inputs: Vec<ScalarInput>,
+    pub(super) outputs: Vec<Button>,
+    pub(super) recurrent: bool,
+    // Genomes written before the view, observation and game were configurable
+    // all used the default ones
+    #[serde(default)]
+    pub(super) view: View,
+    #[serde(default)]
+    pub(super) observation: Observation,
+    #[serde(default)]
+    pub(super) game: Game,
+}
+
+// Errors reading or writing snapshots and genome files
+pub enum SnapshotError {
+    // IO error while reading or writing the file
+    Io(io::Error),
+    // The file is not valid JSON, or doesn't match its version's schema
+    Parse(serde_json::Error),
+    // The file was written by a newer build
+    UnsupportedVersion(u64),
+    // The file has no version and is missing the fields of a version 0
+    // snapshot, so it isn't a snapshot at all
+    NotASnapshot,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(formatter, "IO error: {}", err),
+            SnapshotError::Parse(err) => write!(formatter, "invalid file: {}", err),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                formatter,
+                "unsupported file version {}, written by a newer build",
+                version
+            ),
+            SnapshotError::NotASnapshot => write!(formatter, "not a snapshot"),
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(err: serde_json::Error) -> Self {
+        SnapshotError::Parse(err)
+    }
+}
+
+// Version 0 snapshots only held the pool and the generation, and their
+// individuals stored their nodes as a list of node types
+#[derive(Deserialize)]
+struct AiSnapshotV0 {
+    pool: Vec<SpeciesV0>,
+    generation: u64,
+}
+
+#[derive(Deserialize)]
+struct SpeciesV0 {
+    id: u64,
+    members: Vec<IndividualV0>,
+    staleness: u64,
+    top_fitness: u64,
+}
+
+#[derive(Deserialize)]
+struct IndividualV0 {
+    nodes: Vec<NodeType>,
+    genes: Vec<Gene>,
+    fitness: u64,
+}
+
+// Version 0 nodes all had sigmoid activations, and the networks had no scalar
+// inputs and two outputs (right and A). The random number generator and
+// innovation registry were not saved, so a new generator is seeded and the
+// registry carries on from the highest innovation number and node id in the
+// pool
+fn migrate_from_version_0(snapshot: AiSnapshotV0) -> AiSnapshot {
+    let mut max_fitness = 0;
+    let mut max_innovation_number = None;
+    let mut next_node_id = 0;
+    let mut pool = vec![];
+    for species in snapshot.pool {
+        let mut members = vec![];
+        for individual in species.members {
+            max_fitness = max_fitness.max(individual.fitness);
+            for gene in &individual.genes {
+                max_innovation_number = max_innovation_number.max(Some(gene.innovation_number));
+            }
+            next_node_id = next_node_id.max(individual.nodes.len());
+            members.push(Individual {
+                nodes: individual
+                    .nodes
+                    .into_iter()
+                    .map(Node::new)
+                    .enumerate()
+                    .collect(),
+                genes: individual.genes,
+                fitness: individual.fitness,
+                level_fitnesses: vec![],
+            });
+        }
+        pool.push(Species {
+            id: species.id,
+            members,
+            staleness: species.staleness,
+            top_fitness: species.top_fitness,
+        });
+    }
+
+    let seed = rand::random();
+    println!("Seeding the migrated snapshot's generator with {}", seed);
+    let next_innovation_number = max_innovation_number.map_or(0, |number| number + 1);
+    AiSnapshot {
+        version: SNAPSHOT_VERSION,
+        generation: snapshot.generation,
+        max_fitness,
+        pool,
+        rng: Pcg64::seed_from_u64(seed),
+        innovations: InnovationRegistry::resume(next_innovation_number, next_node_id),
+        options: AiOptions {
+            inputs: vec![],
+            outputs: vec![Button::Right, Button::A],
+            seed: Some(seed),
+            ..AiOptions::default()
+        },
+    }
+}
+
+// Only the version is read at first, to know which format the rest of the
+// file is in. Snapshots and genomes are then deserialized straight from the
+// file rather than through a `serde_json::Value`, which would turn the
+// generator's 128-bit state into a float and lose the weights' precision
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    version: u64,
+}
+
+pub(super) fn read(path: &Path) -> Result<AiSnapshot, SnapshotError> {
+    let bytes = fs::read(path)?;
+    let version = serde_json::from_slice::<Header>(&bytes)?.version;
+    if version > SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    let snapshot = if version < 1 {
+        // Without a version nor the fields of a version 0 snapshot, the file
+        // isn't a snapshot at all
+        let snapshot = serde_json::from_slice(&bytes).map_err(|err| {
+            if err.is_data() {
+                SnapshotError::NotASnapshot
+            } else {
+                SnapshotError::Parse(err)
+            }
+        })?;
+        migrate_from_version_0(snapshot)
+    } else {
+        serde_json::from_slice(&bytes)?
+    };
+    if version < SNAPSHOT_VERSION {
+        println!(
+            "Migrated snapshot {} from version {} to version {}",
+            path.display(),
+            version,
+            SNAPSHOT_VERSION
+        );
+    }
+    Ok(snapshot)
+}
+
+pub(super) fn read_genome(path: &Path) -> Result<Genome, SnapshotError> {
+    let bytes = fs::read(path)?;
+    let version = serde_json::from_slice::<Header>(&bytes)?.version;
+    if version > GENOME_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+// Writes to a temporary file that is then renamed, so that an interrupted
+// write never leaves a truncated file behind
+pub(super) fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), SnapshotError> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let temporary_path = path.with_extension("json.tmp");
+    let file = File::create(&temporary_path)?;
+    let mut writer = BufWriter::new(&file);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()?;
+    drop(writer);
+    file.sync_all()?;
+    fs::rename(&temporary_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Activation, Ai, EvolutionConfig};
+    use super::*;
+
+    use rand::Rng;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "mario-neural-network-{}-{}",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn read_written_snapshot() {
+        let directory = temporary_directory("read-written-snapshot");
+        let options = AiOptions {
+            seed: Some(0),
+            evolution: EvolutionConfig {
+                population: 10,
+                ..EvolutionConfig::default()
+            },
+            snapshot_directory: directory.to_string_lossy().into_owned(),
+            ..AiOptions::default()
+        };
+        let ai = Ai::new(options);
+        ai.save_snapshot().ok().unwrap();
+        let snapshot = read(&directory.join("g-0.json")).ok().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(snapshot.generation, ai.generation);
+        assert_eq!(
+            serde_json::to_string(&snapshot.rng).unwrap(),
+            serde_json::to_string(&ai.rng).unwrap()
+        );
+        let members = |pool: &[Species]| pool.iter().map(Species::len).collect::<Vec<_>>();
+        assert_eq!(members(&snapshot.pool), members(&ai.pool));
+    }
+
+    // An individual with many random weights
+    fn random_individual(rng: &mut Pcg64) -> Individual {
+        let genes = (0..10_000)
+            .map(|innovation_number| Gene {
+                in_node: 0,
+                out_node: 1,
+                weight: rng.gen_range(-2.0, 2.0),
+                enabled: true,
+                innovation_number,
+            })
+            .collect();
+        Individual {
+            genes,
+            ..Individual::default()
+        }
+    }
+
+    fn weights(individual: &Individual) -> Vec<u64> {
+        individual
+            .genes
+            .iter()
+            .map(|gene| gene.weight.to_bits())
+            .collect()
+    }
+
+    #[test]
+    fn read_weights_unchanged() {
+        let mut rng = Pcg64::seed_from_u64(0);
+        let individual = random_individual(&mut rng);
+        let snapshot = AiSnapshot {
+            version: SNAPSHOT_VERSION,
+            generation: 0,
+            max_fitness: 0,
+            pool: vec![Species {
+                members: vec![individual],
+                ..Species::default()
+            }],
+            rng,
+            innovations: InnovationRegistry::new(0),
+            options: AiOptions::default(),
+        };
+        let directory = temporary_directory("read-weights-unchanged");
+        let path = directory.join("g-0.json");
+        write(&path, &snapshot).ok().unwrap();
+        let read_snapshot = read(&path).ok().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            weights(&read_snapshot.pool[0].members[0]),
+            weights(&snapshot.pool[0].members[0])
+        );
+    }
+
+    #[test]
+    fn read_genome_weights_unchanged() {
+        let genome = Genome {
+            version: GENOME_VERSION,
+            generation: 0,
+            fitness: 0,
+            individual: random_individual(&mut Pcg64::seed_from_u64(0)),
+            inputs: vec![],
+            outputs: vec![Button::Right],
+            recurrent: false,
+            view: View::default(),
+            observation: Observation::default(),
+            game: Game::default(),
+        };
+        let directory = temporary_directory("read-genome-weights-unchanged");
+        let path = directory.join("champion.json");
+        write(&path, &genome).ok().unwrap();
+        let loaded = read_genome(&path).ok().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(weights(&loaded.individual), weights(&genome.individual));
+    }
+
+    #[test]
+    fn migrate_version_0_snapshot() {
+        const SNAPSHOT: &str = r#"{
+            "pool": [{
+                "id": 3,
+                "members": [{
+                    "nodes": ["Input", "Output", "Hidden"],
+                    "genes": [
+                        {"in_node": 0, "out_node": 2, "weight": 0.5, "enabled": true, "innovation_number": 7},
+                        {"in_node": 2, "out_node": 1, "weight": -1.5, "enabled": false, "innovation_number": 2}
+                    ],
+                    "fitness": 120
+                }],
+                "staleness": 1,
+                "top_fitness": 150
+            }],
+            "generation": 4
+        }"#;
+        let directory = temporary_directory("migrate-version-0-snapshot");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("g-4.json");
+        fs::write(&path, SNAPSHOT).unwrap();
+        let snapshot = read(&path).ok().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(snapshot.generation, 4);
+        assert_eq!(snapshot.max_fitness, 120);
+        assert_eq!(snapshot.options.outputs, vec![Button::Right, Button::A]);
+        let species = &snapshot.pool[0];
+        assert_eq!(
+            (species.id, species.staleness, species.top_fitness),
+            (3, 1, 150)
+        );
+        let individual = &species.members[0];
+        assert_eq!(individual.fitness, 120);
+        assert_eq!(individual.nodes.len(), 3);
+        assert!(individual.nodes[&1].is_output_node());
+        assert!(individual
+            .nodes
+            .values()
+            .all(|node| node.activation == Activation::Sigmoid));
+        assert_eq!(individual.genes[1].weight, -1.5);
+        let innovations = serde_json::to_value(&snapshot.innovations).unwrap();
+        assert_eq!(innovations["next_innovation_number"], 8);
+        assert_eq!(innovations["next_node_id"], 3);
+    }
+
+    #[test]
+    fn reject_files_that_are_not_snapshots() {
+        let directory = temporary_directory("reject-files-that-are-not-snapshots");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.json");
+        fs::write(&path, r#"{"emulator": {"headless": true}}"#).unwrap();
+        let result = read(&path);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(matches!(result, Err(SnapshotError::NotASnapshot)));
+    }
+}
@@ -1,45 +1,187 @@
 extern crate mario_neural_network;
 
-use mario_neural_network::{ai, dashboard, nes, start, EmulatorOptions};
+use mario_neural_network::ai::Ai;
+use mario_neural_network::config::Config;
+use mario_neural_network::{replay_genome, replay_individual, train};
 
-use ai::AiOptions;
-use dashboard::DashboardOptions;
-use nes::gfx::Scale;
-use nes::rom::Rom;
-
-use std::fs::File;
+use std::env;
 use std::path::Path;
+use std::process;
 
-// Emulator options
-const ROM_PATH: &'static str = "super_mario.nes";
-const SCALE: Scale = Scale::Scale3x;
-const SAVE_STATE_PATH: &'static str = "state.sav";
-const VSYNC: bool = true;
+const USAGE: &'static str = "Usage:
+    mario_neural_network train [--config <config>]
+    mario_neural_network resume <snapshot> [--config <config>]
+    mario_neural_network replay <genome> [--config <config>]
+    mario_neural_network replay <snapshot> --species <S> --individual <I> [--config <config>]
+    mario_neural_network inspect <snapshot>
 
-// AI options
-const STUCK_TIMEOUT_MS: u64 = 500;
-const FINISH_TIMEOUT_MS: u64 = 20_000;
+Without --config, resume and replay use the config.json saved next to the
+snapshot or genome if there is one. Otherwise every option takes its default
+value.";
 
-// Dashboard options
-const HOST: &'static str = "localhost";
-const PORT: u64 = 8080;
+enum Command {
+    Train,
+    Resume(String),
+    ReplayGenome(String),
+    ReplayIndividual {
+        snapshot: String,
+        species: usize,
+        individual: usize,
+    },
+    Inspect(String),
+}
 
-fn main() {
-    let rom = Rom::load(&mut File::open(&Path::new(ROM_PATH)).unwrap()).unwrap();
-    start(
-        EmulatorOptions {
-            rom,
-            scale: SCALE,
-            save_state_path: SAVE_STATE_PATH,
-            vsync: VSYNC,
-        },
-        AiOptions {
-            stuck_timeout_ms: STUCK_TIMEOUT_MS,
-            finish_timeout_ms: FINISH_TIMEOUT_MS,
+struct Arguments {
+    command: Command,
+    config: Option<String>,
+}
+
+fn parse_index(flag: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or(format!("Missing {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+fn parse_arguments(mut args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+    let command = args.next().ok_or("Missing command")?;
+    let mut positional = vec![];
+    let mut config = None;
+    let mut species = None;
+    let mut individual = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config = Some(args.next().ok_or("Missing value for --config")?),
+            "--species" => species = Some(args.next().ok_or("Missing value for --species")?),
+            "--individual" => {
+                individual = Some(args.next().ok_or("Missing value for --individual")?)
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let mut file = |name: &str| positional.next().ok_or(format!("Missing {}", name));
+    let command = match command.as_str() {
+        "train" => Command::Train,
+        "resume" => Command::Resume(file("snapshot")?),
+        "replay" if species.is_none() && individual.is_none() => {
+            Command::ReplayGenome(file("genome")?)
+        }
+        "replay" => Command::ReplayIndividual {
+            snapshot: file("snapshot")?,
+            species: parse_index("--species", species)?,
+            individual: parse_index("--individual", individual)?,
         },
-        DashboardOptions {
-            host: HOST,
-            port: PORT
+        "inspect" => Command::Inspect(file("snapshot")?),
+        _ => return Err(format!("Unknown command {}", command)),
+    };
+    if let Some(arg) = positional.next() {
+        return Err(format!("Unexpected argument {}", arg));
+    }
+    Ok(Arguments { command, config })
+}
+
+// The config given on the command line, or else the one saved next to the
+// snapshot or genome, or else the default config
+fn load_config(path: Option<String>, file: Option<&str>) -> Result<Config, String> {
+    let saved_config = file
+        .and_then(|file| Path::new(file).parent())
+        .map(|directory| directory.join("config.json"))
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned());
+    match path.or(saved_config) {
+        Some(path) => {
+            println!("Using config {}", path);
+            Config::load(&path).map_err(|err| err.to_string())
         }
-    );
+        None => {
+            let config = Config::default();
+            config.validate().map_err(|err| err.to_string())?;
+            Ok(config)
+        }
+    }
+}
+
+fn run(arguments: Arguments) -> Result<(), String> {
+    match arguments.command {
+        Command::Train => train(load_config(arguments.config, None)?, None)?,
+        Command::Resume(snapshot) => {
+            let config = load_config(arguments.config, Some(&snapshot))?;
+            train(config, Some(&snapshot))?
+        }
+        Command::ReplayGenome(genome) => {
+            let config = load_config(arguments.config, Some(&genome))?;
+            replay_genome(config, &genome)?
+        }
+        Command::ReplayIndividual {
+            snapshot,
+            species,
+            individual,
+        } => {
+            let config = load_config(arguments.config, Some(&snapshot))?;
+            replay_individual(config, &snapshot, species, individual)?
+        }
+        Command::Inspect(snapshot) => Ai::from_snapshot(Config::default().ai, &snapshot)
+            .map_err(|err| err.to_string())?
+            .print_statistics(),
+    }
+    Ok(())
+}
+
+fn main() {
+    let arguments = match parse_arguments(env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(arguments) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Arguments, String> {
+        parse_arguments(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parse_replay_individual() {
+        let arguments = parse("replay g-3.json --species 1 --individual 2").unwrap();
+        match arguments.command {
+            Command::ReplayIndividual {
+                snapshot,
+                species,
+                individual,
+            } => assert_eq!((snapshot.as_str(), species, individual), ("g-3.json", 1, 2)),
+            _ => panic!("expected an individual replay"),
+        }
+    }
+
+    #[test]
+    fn reject_options_without_values() {
+        let err = parse("replay g-3.json --species").err().unwrap();
+        assert_eq!(err, "Missing value for --species");
+        let err = parse("replay g-3.json --species 1 --individual")
+            .err()
+            .unwrap();
+        assert_eq!(err, "Missing value for --individual");
+        let err = parse("replay g-3.json --species 1").err().unwrap();
+        assert_eq!(err, "Missing --individual");
+    }
+
+    #[test]
+    fn parse_inspect() {
+        let arguments = parse("inspect g-3.json").unwrap();
+        assert!(
+            matches!(arguments.command, Command::Inspect(ref snapshot) if snapshot == "g-3.json")
+        );
+        assert!(parse("inspect").is_err());
+    }
 }
@@ -0,0 +1,206 @@
+use crate::ai::{AiOptions, Observation, PixelColours, ScalarInput};
+use crate::dashboard::DashboardOptions;
+use crate::EmulatorOptions;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+// Every hyperparameter of a run. Run configs are JSON files, in which any
+// missing key takes its default value
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub emulator: EmulatorOptions,
+    pub ai: AiOptions,
+    pub dashboard: DashboardOptions,
+}
+
+pub enum ConfigError {
+    // IO error while reading the config file
+    Io(io::Error),
+    // The config file is not valid JSON, has an unknown key or a value of
+    // the wrong type
+    Parse(serde_json::Error),
+    // A value is of the right type but out of range
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(formatter, "couldn't read config: {}", err),
+            ConfigError::Parse(err) => write!(formatter, "invalid config: {}", err),
+            ConfigError::Invalid(message) => write!(formatter, "invalid config: {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(err: serde_json::Error) -> Self {
+        ConfigError::Parse(err)
+    }
+}
+
+fn check(condition: bool, message: &str) -> Result<(), ConfigError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(message.to_string()))
+    }
+}
+
+fn check_probability(value: f64, key: &str) -> Result<(), ConfigError> {
+    check(
+        (0.0..=1.0).contains(&value),
+        &format!("{} must be between 0 and 1, got {}", key, value),
+    )
+}
+
+// Configs written before several save states were supported have a single
+// `emulator.save_state_path`. It is still accepted as a one-element
+// `save_state_paths`
+fn migrate_save_state_path(config: &mut Value) -> Result<(), ConfigError> {
+    let emulator = match config.get_mut("emulator").and_then(Value::as_object_mut) {
+        Some(emulator) => emulator,
+        None => return Ok(()),
+    };
+    let path = match emulator.remove("save_state_path") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    check(
+        !emulator.contains_key("save_state_paths"),
+        "emulator.save_state_path and emulator.save_state_paths can't both be set",
+    )?;
+    println!("emulator.save_state_path is deprecated, use emulator.save_state_paths instead");
+    emulator.insert("save_state_paths".to_string(), json!([path]));
+    Ok(())
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, ConfigError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut config: Value = serde_json::from_reader(reader)?;
+        migrate_save_state_path(&mut config)?;
+        let config: Config = serde_json::from_value(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+
+    // Checks the values that deserialization alone can't
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let emulator = &self.emulator;
+        check(
+            Path::new(&emulator.rom_path).is_file(),
+            &format!("emulator.rom_path: {} is not a file", emulator.rom_path),
+        )?;
+        check(
+            !emulator.save_state_paths.is_empty(),
+            "emulator.save_state_paths must not be empty",
+        )?;
+        for path in &emulator.save_state_paths {
+            check(
+                Path::new(path).is_file(),
+                &format!("emulator.save_state_paths: {} is not a file", path),
+            )?;
+        }
+        check(emulator.workers > 0, "emulator.workers must be at least 1")?;
+
+        let ai = &self.ai;
+        check(!ai.outputs.is_empty(), "ai.outputs must not be empty")?;
+        for input in &ai.inputs {
+            if let ScalarInput::DistanceToLevelEnd { level_end_x } = input {
+                let levels = emulator.save_state_paths.len();
+                check(
+                    level_end_x.len() == 1 || level_end_x.len() == levels,
+                    &format!(
+                        "ai.inputs.DistanceToLevelEnd.level_end_x must have 1 or {} values, got {}",
+                        levels,
+                        level_end_x.len()
+                    ),
+                )?;
+            }
+        }
+        if let Observation::Pixels { cell_size, colours } = ai.observation {
+            check(
+                cell_size > 0,
+                "ai.observation.Pixels.cell_size must be at least 1",
+            )?;
+            if let PixelColours::BrightnessLevels { levels } = colours {
+                check(
+                    levels >= 2,
+                    "ai.observation.Pixels.colours.BrightnessLevels.levels must be at least 2",
+                )?;
+            }
+        }
+
+        let evolution = &ai.evolution;
+        check(
+            evolution.population > 0,
+            "ai.evolution.population must be at least 1",
+        )?;
+        check(
+            evolution.max_species > 1,
+            "ai.evolution.max_species must be at least 2",
+        )?;
+        check(
+            evolution.compatibility_threshold > 0.0,
+            "ai.evolution.compatibility_threshold must be positive",
+        )?;
+        check_probability(
+            evolution.crossover_mutation_probability,
+            "ai.evolution.crossover_mutation_probability",
+        )?;
+        check_probability(
+            evolution.interspecies_crossover_probability,
+            "ai.evolution.interspecies_crossover_probability",
+        )?;
+
+        let mutation = &ai.mutation;
+        let rates = [
+            (
+                mutation.weight_perturbation_rate,
+                "weight_perturbation_rate",
+            ),
+            (mutation.weight_replacement_rate, "weight_replacement_rate"),
+            (mutation.add_connection_rate, "add_connection_rate"),
+            (mutation.add_node_rate, "add_node_rate"),
+            (mutation.toggle_gene_rate, "toggle_gene_rate"),
+            (mutation.delete_connection_rate, "delete_connection_rate"),
+            (mutation.prune_node_rate, "prune_node_rate"),
+            (mutation.change_activation_rate, "change_activation_rate"),
+        ];
+        for (rate, key) in &rates {
+            check_probability(*rate, &format!("ai.mutation.{}", key))?;
+        }
+        check(
+            mutation.weight_perturbation_std_dev >= 0.0,
+            "ai.mutation.weight_perturbation_std_dev must not be negative",
+        )?;
+        check(
+            mutation.initial_weight_range > 0.0,
+            "ai.mutation.initial_weight_range must be positive",
+        )?;
+        check(
+            mutation.max_weight > 0.0,
+            "ai.mutation.max_weight must be positive",
+        )?;
+        Ok(())
+    }
+}
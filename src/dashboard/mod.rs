@@ -1,6 +1,7 @@
 use crate::utils::{Tile, Screen};
 
 use crossbeam::channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
 use simple_server::{Method, Server, StatusCode};
 use tungstenite::server::accept;
 
@@ -11,12 +12,22 @@ use std::net::TcpListener;
 use std::path::Path;
 use std::thread::spawn;
 
-#[derive(Copy, Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct DashboardOptions {
-    pub host: &'static str,
+    pub host: String,
     pub port: u64,
 }
 
+impl Default for DashboardOptions {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 8080,
+        }
+    }
+}
+
 impl fmt::Debug for Tile {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         formatter.write_fmt(format_args!("{}", *self as u64))
@@ -25,10 +36,12 @@ impl fmt::Debug for Tile {
 
 enum Message {
     UpdateScreen(Screen),
+    // Fitness on each level
+    UpdateLevels(Vec<u64>),
 }
 
 impl Message {
-    fn serialise_screen(screen: Screen) -> String {
+    fn serialise_screen(screen: &Screen) -> String {
         let data = screen
             .iter()
             .map(|row| format!("{:?}", row))
@@ -39,7 +52,11 @@ impl Message {
 
     fn serialise(&self) -> String {
         match self {
-            Self::UpdateScreen(screen) => Self::serialise_screen(*screen)
+            Self::UpdateScreen(screen) => Self::serialise_screen(screen),
+            Self::UpdateLevels(fitnesses) => format!(
+                "{{ \"event\": \"update_levels\", \"data\": {:?} }}",
+                fitnesses
+            ),
         }
     }
 }
@@ -51,12 +68,14 @@ pub struct Dashboard {
 impl Dashboard {
     pub fn new(options: DashboardOptions) -> Dashboard {
         let (tx, rx) = unbounded();
-        spawn(move || Dashboard::run_http_server(options.host, options.port));
-        spawn(move || Dashboard::run_websockets_server(options.host, rx));
+        let DashboardOptions { host, port } = options;
+        let http_host = host.clone();
+        spawn(move || Dashboard::run_http_server(http_host, port));
+        spawn(move || Dashboard::run_websockets_server(host, rx));
         Dashboard { sender: tx }
     }
 
-    fn run_http_server(host: &'static str, port: u64) {
+    fn run_http_server(host: String, port: u64) {
         let mut server =
             Server::new(
                 |request, mut response| match (request.method(), request.uri().path()) {
@@ -79,10 +98,10 @@ impl Dashboard {
         server.set_static_directory("dashboard/");
         println!("Dashboard listening on http://{}:{}", host, port);
         let port = format!("{}", port);
-        server.listen(host, &port);
+        server.listen(&host, &port);
     }
 
-    fn run_websockets_server(host: &'static str, rx: Receiver<Message>) {
+    fn run_websockets_server(host: String, rx: Receiver<Message>) {
         use tungstenite::Message::Text;
         let port = 9000;
         let addr = format!("{}:{}", host, port);
@@ -102,4 +121,10 @@ impl Dashboard {
     pub fn update_screen(&self, screen: Screen) {
         self.sender.send(Message::UpdateScreen(screen)).unwrap();
     }
+
+    pub fn update_levels(&self, fitnesses: &[u64]) {
+        self.sender
+            .send(Message::UpdateLevels(fitnesses.to_vec()))
+            .unwrap();
+    }
 }
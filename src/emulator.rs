@@ -0,0 +1,67 @@
+use crate::nes::cpu::Cpu;
+use crate::nes::input::Input;
+use crate::nes::mapper::{create_mapper, Mapper};
+use crate::nes::mem::MemMap;
+use crate::nes::ppu::{Oam, Ppu, Vram};
+use crate::nes::rom::Rom;
+use crate::nes::util::SaveState;
+
+use std::fs::File;
+use std::path::Path;
+
+// A headless NES booted from in-memory save states, one per level that
+// individuals are evaluated on. Emulators own all of their state, so several
+// of them can run side by side on different threads.
+pub struct Emulator {
+    pub cpu: Cpu<MemMap>,
+    save_states: Vec<SaveState>,
+}
+
+impl Emulator {
+    pub fn new(rom: Rom, save_states: Vec<SaveState>) -> Emulator {
+        let mapper: Box<dyn Mapper + Send> = create_mapper(Box::new(rom));
+        let ppu = Ppu::new(Vram::new(mapper), Oam::new());
+        let memmap = MemMap::new(ppu, Input::new());
+        let mut cpu = Cpu::new(memmap);
+
+        cpu.reset();
+
+        let mut emulator = Emulator { cpu, save_states };
+        emulator.reset(0);
+        emulator
+    }
+
+    pub fn load_save_state(path: &str) -> SaveState {
+        SaveState::read(&mut File::open(Path::new(path)).unwrap()).unwrap()
+    }
+
+    pub fn levels(&self) -> usize {
+        self.save_states.len()
+    }
+
+    // Restores the save state of the given level. Buttons are released too,
+    // as they are not part of the save state and would otherwise leak from
+    // one run into the next
+    pub fn reset(&mut self, level: usize) {
+        self.save_states[level].restore(&mut self.cpu);
+        self.cpu.mem.input.gamepad.release_all();
+    }
+
+    // Runs the CPU and PPU until the PPU has finished drawing a frame
+    pub fn step_frame(&mut self) {
+        loop {
+            self.cpu.step();
+
+            let ppu_result = self.cpu.mem.ppu.step(self.cpu.cy);
+            if ppu_result.vblank_nmi {
+                self.cpu.nmi();
+            } else if ppu_result.scanline_irq {
+                self.cpu.irq();
+            }
+
+            if ppu_result.new_frame {
+                return;
+            }
+        }
+    }
+}
@@ -8,117 +8,324 @@ extern crate sdl2;
 extern crate simple_server;
 extern crate tungstenite;
 extern crate rand;
+extern crate rand_distr;
+extern crate rand_pcg;
 extern crate serde;
 extern crate serde_json;
 
 pub mod ai;
+pub mod config;
 pub mod dashboard;
+pub mod emulator;
 pub mod nes;
+mod signal;
 mod utils;
 
-use ai::{Ai, AiOptions};
-use dashboard::{Dashboard, DashboardOptions};
-use nes::cpu::Cpu;
+use ai::{Ai, Controller, GenerationResult};
+use config::Config;
+use dashboard::Dashboard;
+use emulator::Emulator;
 use nes::gfx::{Gfx, GfxOptions, Scale};
-use nes::input::Input;
-use nes::mapper::{create_mapper, Mapper};
-use nes::mem::MemMap;
-use nes::ppu::{Oam, Ppu, Vram};
 use nes::rom::Rom;
-use nes::util::Save;
+use nes::util::SaveState;
+use serde::{Deserialize, Serialize};
 
-use std::cell::RefCell;
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::Path;
-use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct EmulatorOptions {
-    pub rom: Rom,
+    pub rom_path: String,
     pub scale: Scale,
-    pub save_state_path: &'static str,
+    // Individuals are evaluated on each of these save states (levels) in
+    // turn, and their fitnesses are combined
+    pub save_state_paths: Vec<String>,
     pub vsync: bool,
+    // Run without opening a window. Headless runs are stopped with SIGINT or
+    // SIGTERM, or once `max_frames` frames have been emulated
+    pub headless: bool,
+    pub max_frames: Option<u64>,
+    // Number of emulators evaluating individuals in parallel. With more than
+    // one worker no window is opened and the dashboard follows the first
+    // worker
+    pub workers: usize,
 }
 
-fn init_emulator(options: EmulatorOptions) -> (Cpu<MemMap>, Gfx) {
-    let rom = Box::new(options.rom);
+impl Default for EmulatorOptions {
+    fn default() -> Self {
+        Self {
+            rom_path: "super_mario.nes".to_string(),
+            scale: Scale::Scale3x,
+            save_state_paths: vec!["state.sav".to_string()],
+            vsync: true,
+            headless: false,
+            max_frames: None,
+            workers: 1,
+        }
+    }
+}
+
+const DASHBOARD_UPDATE_INTERVAL: Duration = Duration::from_millis(30);
+
+fn load_rom(path: &str) -> Rom {
+    Rom::load(&mut File::open(path).unwrap()).unwrap()
+}
 
-    let (gfx, sdl) = Gfx::new(GfxOptions {
-        scale: options.scale,
-        vsync: options.vsync,
-    });
+fn load_save_states(options: &EmulatorOptions) -> Vec<SaveState> {
+    options
+        .save_state_paths
+        .iter()
+        .map(|path| Emulator::load_save_state(path))
+        .collect()
+}
+
+// Trains a new population, or resumes the run that took the given snapshot
+pub fn train(config: Config, snapshot: Option<&str>) -> Result<(), String> {
+    let mut ai = match snapshot {
+        Some(snapshot) => Ai::resume(config.ai, snapshot).map_err(|err| err.to_string())?,
+        None => Ai::new(config.ai),
+    };
 
-    let mapper: Box<dyn Mapper + Send> = create_mapper(rom);
-    let mapper = Rc::new(RefCell::new(mapper));
-    let ppu = Ppu::new(Vram::new(mapper.clone()), Oam::new());
-    let input = Input::new(sdl);
-    let memmap = MemMap::new(ppu, input, mapper);
-    let mut cpu = Cpu::new(memmap);
+    // Keep the effective config next to the snapshots it produces, including
+    // the options taken from the snapshot being resumed
+    let config = Config {
+        ai: ai.options(),
+        ..config
+    };
+    let snapshot_directory = Path::new(&config.ai.snapshot_directory);
+    fs::create_dir_all(snapshot_directory)
+        .and_then(|_| config.save(&snapshot_directory.join("config.json")))
+        .map_err(|err| format!("Couldn't save the config: {}", err))?;
 
-    cpu.reset();
+    signal::install_handlers();
+    let rom = load_rom(&config.emulator.rom_path);
+    let dashboard = Dashboard::new(config.dashboard);
 
-    cpu.load(&mut File::open(&Path::new(options.save_state_path)).unwrap());
+    if config.emulator.workers > 1 {
+        run_parallel(config.emulator, rom, &mut ai, &dashboard);
+    } else {
+        run_sequential(config.emulator, rom, &mut ai, &dashboard);
+    }
+    Ok(())
+}
 
-    (cpu, gfx)
+// Plays an individual from a snapshot
+pub fn replay_individual(
+    config: Config,
+    snapshot: &str,
+    species_index: usize,
+    individual_index: usize,
+) -> Result<(), String> {
+    let ai = Ai::from_snapshot(config.ai.clone(), snapshot).map_err(|err| err.to_string())?;
+    let controller = ai.controller(species_index, individual_index)?;
+    play(config, controller);
+    Ok(())
 }
 
-pub fn start(
-    emulator_options: EmulatorOptions,
-    ai_options: AiOptions,
-    dashboard_options: DashboardOptions,
-) {
-    let save_state_path = emulator_options.save_state_path;
-    let (mut cpu, mut gfx) = init_emulator(emulator_options);
-    let mut ai = Ai::new(ai_options);
-    // ai.load_snapshot("snapshots/g-1.json");
-    let dashboard = Dashboard::new(dashboard_options);
+// Plays a genome file, such as an exported champion
+pub fn replay_genome(config: Config, genome: &str) -> Result<(), String> {
+    let controller = Controller::load(genome, &config.ai).map_err(|err| err.to_string())?;
+    play(config, controller);
+    Ok(())
+}
 
+// Plays a controller on every level in turn, until it dies, gets stuck or
+// succeeds on each of them, streaming its view to the dashboard
+fn play(config: Config, mut controller: Controller) {
+    signal::install_handlers();
+    let dashboard = Dashboard::new(config.dashboard);
+    let options = config.emulator;
+    let mut gfx = if options.headless {
+        None
+    } else {
+        Some(Gfx::new(GfxOptions {
+            scale: options.scale,
+            vsync: options.vsync,
+        }))
+    };
+    let save_states = load_save_states(&options);
+    let mut emulator = Emulator::new(load_rom(&options.rom_path), save_states);
     let mut last_dashboard_update = Instant::now();
-    let dashboard_update_interval = Duration::from_millis(30);
+    let mut level = 0;
+    let mut level_fitnesses = vec![];
 
     loop {
-        cpu.step();
+        emulator.step_frame();
 
-        let ppu_result = cpu.mem.ppu.step(cpu.cy);
-        if ppu_result.vblank_nmi {
-            cpu.nmi();
-        } else if ppu_result.scanline_irq {
-            cpu.irq();
+        if let Some(ref mut gfx) = gfx {
+            gfx.tick();
+            gfx.composite(&mut emulator.cpu.mem.ppu.screen);
         }
 
-        if ppu_result.new_frame {
+        let outcome = if controller.has_succeeded() {
+            Some("succeeded")
+        } else if controller.is_stuck() {
+            Some("was stuck")
+        } else if controller.is_dead() {
+            Some("died")
+        } else {
+            None
+        };
+        if let Some(outcome) = outcome {
+            let fitness = controller.fitness();
+            println!(
+                "Level {}: individual {} (fitness = {})",
+                level, outcome, fitness
+            );
+            level_fitnesses.push(fitness);
+            dashboard.update_levels(&level_fitnesses);
+            level += 1;
+            if level == emulator.levels() {
+                let fitness = config.ai.fitness_aggregation.aggregate(&level_fitnesses);
+                println!("Fitness = {}", fitness);
+                return;
+            }
+            emulator.reset(level);
+            controller.restart(level);
+            continue;
+        }
+
+        controller.update_game_state(&mut emulator.cpu);
+        controller
+            .get_inputs()
+            .apply(&mut emulator.cpu.mem.input.gamepad);
+
+        if last_dashboard_update.elapsed() > DASHBOARD_UPDATE_INTERVAL {
+            dashboard.update_screen(controller.get_screen());
+            last_dashboard_update = Instant::now();
+        }
+
+        let window_closed = match gfx {
+            Some(ref mut gfx) => gfx.shutdown_requested(),
+            None => false,
+        };
+        if window_closed || signal::shutdown_requested() {
+            return;
+        }
+    }
+}
+
+fn run_sequential(options: EmulatorOptions, rom: Rom, ai: &mut Ai, dashboard: &Dashboard) {
+    let mut gfx = if options.headless {
+        None
+    } else {
+        Some(Gfx::new(GfxOptions {
+            scale: options.scale,
+            vsync: options.vsync,
+        }))
+    };
+    let max_frames = options.max_frames;
+    let mut emulator = Emulator::new(rom, load_save_states(&options));
+
+    let mut last_dashboard_update = Instant::now();
+    let mut frames: u64 = 0;
+
+    loop {
+        emulator.step_frame();
+        frames += 1;
+
+        if let Some(ref mut gfx) = gfx {
             gfx.tick();
-            gfx.composite(&mut *cpu.mem.ppu.screen);
+            gfx.composite(&mut emulator.cpu.mem.ppu.screen);
+        }
 
-            if ai.has_succeeded() {
+        if ai.has_succeeded() || ai.is_stuck() || ai.is_dead() {
+            let reason = if ai.has_succeeded() {
+                "succeeded"
+            } else if ai.is_stuck() {
+                "was stuck"
+            } else {
+                "died"
+            };
+            let msg = format!(
+                "Reset because AI {} on level {}",
+                reason,
+                ai.current_level()
+            );
+            println!("{}", msg);
+            if let Some(ref mut gfx) = gfx {
+                gfx.status_line.set(msg);
+            }
+            let generation = ai.generation();
+            if ai.finish_level(&mut emulator) {
                 println!("AI succeeded");
-                // TODO: Save successful neural network
                 break;
-            } else if ai.is_stuck() || ai.is_dead() {
-                cpu.load(&mut File::open(&Path::new(save_state_path)).unwrap());
-                let reason = if ai.is_stuck() { "was stuck" } else { "died" };
-                let msg = format!("Reset because AI {}", reason).to_string();
-                println!("{}", msg);
-                gfx.status_line.set(msg);
-                ai.next_individual();
-                continue;
             }
+            if ai.generation() != generation {
+                dashboard.update_levels(ai.best_level_fitnesses());
+            }
+            if ai.is_finished() {
+                println!("Reached generation limit");
+                break;
+            }
+            continue;
+        }
 
-            ai.update_game_state(&mut cpu);
-            // ai.debug_game_state();
+        ai.update_game_state(&mut emulator.cpu);
+        // ai.debug_game_state();
 
-            let ai_inputs = ai.get_inputs();
-            cpu.mem.input.gamepad.right = ai_inputs.right;
-            cpu.mem.input.gamepad.a = ai_inputs.a;
+        let ai_inputs = ai.get_inputs();
+        ai_inputs.apply(&mut emulator.cpu.mem.input.gamepad);
 
-            if last_dashboard_update.elapsed() > dashboard_update_interval {
-                dashboard.update_screen(ai.get_screen());
-                last_dashboard_update = Instant::now();
+        if last_dashboard_update.elapsed() > DASHBOARD_UPDATE_INTERVAL {
+            dashboard.update_screen(ai.get_screen());
+            last_dashboard_update = Instant::now();
+        }
+
+        let window_closed = match gfx {
+            Some(ref mut gfx) => gfx.shutdown_requested(),
+            None => false,
+        };
+        let out_of_frames = max_frames.is_some_and(|max_frames| frames >= max_frames);
+        if window_closed || signal::shutdown_requested() || out_of_frames {
+            break;
+        }
+    }
+}
+
+fn run_parallel(options: EmulatorOptions, rom: Rom, ai: &mut Ai, dashboard: &Dashboard) {
+    let save_states = load_save_states(&options);
+    let mut emulators: Vec<Emulator> = (0..options.workers)
+        .map(|_| Emulator::new(rom.clone(), save_states.clone()))
+        .collect();
+    println!("Evaluating individuals on {} emulators", emulators.len());
+
+    let max_frames = options.max_frames;
+    let frames = AtomicU64::new(0);
+    let last_dashboard_update = Mutex::new(Instant::now());
+    // Called by every worker after each emulated frame. Returns false once the
+    // run should stop
+    let on_frame = |worker: usize, ai_screen| {
+        if worker == 0 {
+            let mut last_dashboard_update = last_dashboard_update.lock().unwrap();
+            if last_dashboard_update.elapsed() > DASHBOARD_UPDATE_INTERVAL {
+                dashboard.update_screen(ai_screen);
+                *last_dashboard_update = Instant::now();
             }
+        }
+        let frames = frames.fetch_add(1, Ordering::Relaxed) + 1;
+        let out_of_frames = max_frames.is_some_and(|max_frames| frames >= max_frames);
+        !(signal::shutdown_requested() || out_of_frames)
+    };
 
-            if cpu.mem.input.shutdown_requested() {
+    loop {
+        match ai.evaluate_generation(&mut emulators, &on_frame) {
+            GenerationResult::Succeeded => {
+                println!("AI succeeded");
                 break;
             }
+            GenerationResult::Interrupted => break,
+            GenerationResult::Completed => {
+                dashboard.update_levels(ai.best_level_fitnesses());
+                if ai.is_finished() {
+                    println!("Reached generation limit");
+                    break;
+                }
+            }
         }
     }
 }
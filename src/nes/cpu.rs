@@ -5,7 +5,7 @@
 use super::mem::Mem;
 use super::util::Save;
 
-use std::fs::File;
+use std::io::{Read, Write};
 use std::ops::Deref;
 
 #[cfg(cpuspew)]
@@ -706,13 +706,13 @@ impl<M: Mem> Mem for Cpu<M> {
 }
 
 impl<M: Mem + Save> Save for Cpu<M> {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         self.cy.save(fd);
         self.regs.save(fd);
         self.mem.save(fd);
     }
 
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         self.cy.load(fd);
         self.regs.load(fd);
         self.mem.load(fd);
@@ -2,8 +2,11 @@
 // Author: Patrick Walton
 //
 
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
 use sdl2::render::{Canvas, Texture, TextureAccess};
 use sdl2::Sdl;
+use serde::{Deserialize, Serialize};
 
 /// Emulated screen width in pixels
 const SCREEN_WIDTH: usize = 256;
@@ -362,7 +365,7 @@ impl StatusLine {
 // Screen scaling
 //
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum Scale {
     Scale1x,
     Scale2x,
@@ -384,6 +387,7 @@ pub struct Gfx {
     pub texture: Texture<'static>,
     pub scale: Scale,
     pub status_line: StatusLine,
+    sdl: Sdl,
     _texture_creator: TextureCreator<WindowContext>,
 }
 
@@ -393,7 +397,7 @@ pub struct GfxOptions {
 }
 
 impl Gfx {
-    pub fn new(options: GfxOptions) -> (Gfx, Sdl) {
+    pub fn new(options: GfxOptions) -> Gfx {
         let scale = options.scale;
 
         // FIXME: Handle SDL better
@@ -427,15 +431,25 @@ impl Gfx {
             )
             .unwrap();
 
-        (
-            Gfx {
-                renderer: Box::new(renderer),
-                texture,
-                scale,
-                status_line: StatusLine::new(),
-                _texture_creator: texture_creator,
-            },
+        Gfx {
+            renderer: Box::new(renderer),
+            texture,
+            scale,
+            status_line: StatusLine::new(),
             sdl,
+            _texture_creator: texture_creator,
+        }
+    }
+
+    /// Returns true if the window was closed or Escape was pressed.
+    pub fn shutdown_requested(&mut self) -> bool {
+        matches!(
+            self.sdl.event_pump().unwrap().poll_event(),
+            Some(Event::Quit { .. })
+                | Some(Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                })
         )
     }
 
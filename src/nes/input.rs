@@ -4,9 +4,7 @@
 
 use super::mem::Mem;
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::Sdl;
+use serde::{Deserialize, Serialize};
 
 use std::ops::Deref;
 
@@ -68,6 +66,19 @@ impl StrobeState {
 // The standard NES game pad state
 //
 
+/// A button on the standard NES game pad
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
 pub struct GamepadState {
     pub left: bool,
     pub down: bool,
@@ -81,13 +92,38 @@ pub struct GamepadState {
     strobe_state: StrobeState,
 }
 
+impl GamepadState {
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        match button {
+            Button::A => self.a = pressed,
+            Button::B => self.b = pressed,
+            Button::Select => self.select = pressed,
+            Button::Start => self.start = pressed,
+            Button::Up => self.up = pressed,
+            Button::Down => self.down = pressed,
+            Button::Left => self.left = pressed,
+            Button::Right => self.right = pressed,
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.left = false;
+        self.down = false;
+        self.up = false;
+        self.right = false;
+        self.a = false;
+        self.b = false;
+        self.select = false;
+        self.start = false;
+    }
+}
+
 pub struct Input {
     pub gamepad: GamepadState,
-    sdl: Sdl, // FIXME: Use a `&'a mut EventPump` instead
 }
 
 impl Input {
-    pub fn new(sdl: Sdl) -> Input {
+    pub fn new() -> Input {
         Input {
             gamepad: GamepadState {
                 left: false,
@@ -103,19 +139,13 @@ impl Input {
                     val: STROBE_STATE_A,
                 },
             },
-            sdl: sdl,
         }
     }
+}
 
-    pub fn shutdown_requested(&mut self) -> bool {
-        match self.sdl.event_pump().unwrap().poll_event() {
-            Some(Event::Quit { .. })
-            | Some(Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            }) => true,
-            _ => false,
-        }
+impl Default for Input {
+    fn default() -> Input {
+        Input::new()
     }
 }
 
@@ -6,7 +6,9 @@
 //
 
 use super::rom::Rom;
+use super::util::Save;
 
+use std::io::{Read, Write};
 use std::ops::Deref;
 
 #[derive(PartialEq, Eq)]
@@ -15,7 +17,9 @@ pub enum MapperResult {
     Irq,
 }
 
-pub trait Mapper {
+// Mappers save their registers and RAM, but not the ROM, so that restoring a
+// save state also restores the banks that were mapped in
+pub trait Mapper: Save {
     fn prg_loadb(&mut self, addr: u16) -> u8;
     fn prg_storeb(&mut self, addr: u16, val: u8);
     fn chr_loadb(&mut self, addr: u16) -> u8;
@@ -63,6 +67,13 @@ impl Mapper for Nrom {
     }
 }
 
+// NROM has no registers, so its save states are the same as they were before
+// mappers were saved
+impl Save for Nrom {
+    fn save(&mut self, _: &mut dyn Write) {}
+    fn load(&mut self, _: &mut dyn Read) {}
+}
+
 //
 // Mapper 1 (SxROM/MMC1)
 //
@@ -214,6 +225,31 @@ impl Mapper for SxRom {
     }
 }
 
+save_struct!(SxCtrl { val });
+save_struct!(SxRegs {
+    ctrl,
+    chr_bank_0,
+    chr_bank_1,
+    prg_bank
+});
+
+impl Save for SxRom {
+    fn save(&mut self, fd: &mut dyn Write) {
+        self.regs.save(fd);
+        self.accum.save(fd);
+        self.write_count.save(fd);
+        let mut chr_ram: &mut [u8] = &mut *self.chr_ram;
+        chr_ram.save(fd);
+    }
+    fn load(&mut self, fd: &mut dyn Read) {
+        self.regs.load(fd);
+        self.accum.load(fd);
+        self.write_count.load(fd);
+        let mut chr_ram: &mut [u8] = &mut *self.chr_ram;
+        chr_ram.load(fd);
+    }
+}
+
 //
 // Mapper 4 (TxROM/MMC3)
 //
@@ -402,3 +438,41 @@ impl Mapper for TxRom {
         MapperResult::Continue
     }
 }
+
+save_struct!(TxBankSelect { val });
+save_struct!(TxRegs { bank_select });
+
+impl Save for TxRom {
+    fn save(&mut self, fd: &mut dyn Write) {
+        self.regs.save(fd);
+        let mut prg_ram: &mut [u8] = &mut *self.prg_ram;
+        prg_ram.save(fd);
+        let mut chr_banks_2k: &mut [u8] = &mut self.chr_banks_2k;
+        chr_banks_2k.save(fd);
+        let mut chr_banks_1k: &mut [u8] = &mut self.chr_banks_1k;
+        chr_banks_1k.save(fd);
+        let mut prg_banks: &mut [u8] = &mut self.prg_banks;
+        prg_banks.save(fd);
+        self.scanline_counter.save(fd);
+        self.irq_reload.save(fd);
+        // Saved as a byte, as `bool`'s `Save` doesn't read back what it wrote
+        let mut irq_enabled = self.irq_enabled as u8;
+        irq_enabled.save(fd);
+    }
+    fn load(&mut self, fd: &mut dyn Read) {
+        self.regs.load(fd);
+        let mut prg_ram: &mut [u8] = &mut *self.prg_ram;
+        prg_ram.load(fd);
+        let mut chr_banks_2k: &mut [u8] = &mut self.chr_banks_2k;
+        chr_banks_2k.load(fd);
+        let mut chr_banks_1k: &mut [u8] = &mut self.chr_banks_1k;
+        chr_banks_1k.load(fd);
+        let mut prg_banks: &mut [u8] = &mut self.prg_banks;
+        prg_banks.load(fd);
+        self.scanline_counter.load(fd);
+        self.irq_reload.load(fd);
+        let mut irq_enabled = 0u8;
+        irq_enabled.load(fd);
+        self.irq_enabled = irq_enabled != 0;
+    }
+}
@@ -3,14 +3,11 @@
 //
 
 use super::input::Input;
-use super::mapper::Mapper;
 use super::ppu::Ppu;
 use super::util::Save;
 
-use std::cell::RefCell;
-use std::fs::File;
+use std::io::{Read, Write};
 use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
 
 //
 // The memory interface
@@ -68,10 +65,10 @@ impl Mem for Ram {
 }
 
 impl Save for Ram {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         (&mut **self as &mut [u8]).save(fd);
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         (&mut **self as &mut [u8]).load(fd);
     }
 }
@@ -80,25 +77,24 @@ impl Save for Ram {
 // The main CPU memory map
 //
 
+// The mapper is owned by the PPU's VRAM, which reads CHR data through it on
+// every scanline, and PRG accesses go through the PPU to reach it
 pub struct MemMap {
     pub ram: Ram,
     pub ppu: Ppu,
     pub input: Input,
-    pub mapper: Rc<RefCell<Box<dyn Mapper + Send>>>,
 }
 
 impl MemMap {
     pub fn new(
         ppu: Ppu,
         input: Input,
-        mapper: Rc<RefCell<Box<dyn Mapper + Send>>>,
         // apu: Apu,
     ) -> MemMap {
         MemMap {
             ram: Ram { val: [0; 0x800] },
             ppu: ppu,
             input: input,
-            mapper: mapper,
             // apu: apu,
         }
     }
@@ -118,8 +114,7 @@ impl Mem for MemMap {
         } else if addr < 0x6000 {
             0 // FIXME: I think some mappers use regs in this area?
         } else {
-            let mut mapper = self.mapper.borrow_mut();
-            mapper.prg_loadb(addr)
+            self.ppu.mapper().prg_loadb(addr)
         }
     }
     fn storeb(&mut self, addr: u16, val: u8) {
@@ -134,8 +129,7 @@ impl Mem for MemMap {
         } else if addr < 0x6000 {
             // Nothing. FIXME: I think some mappers use regs in this area?
         } else {
-            let mut mapper = self.mapper.borrow_mut();
-            mapper.prg_storeb(addr, val)
+            self.ppu.mapper().prg_storeb(addr, val)
         }
     }
 }
@@ -6,10 +6,8 @@ use super::mapper::{Mapper, MapperResult};
 use super::mem::Mem;
 use super::util::Save;
 
-use std::cell::RefCell;
-use std::fs::File;
+use std::io::{Read, Write};
 use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
 
 pub const SCREEN_WIDTH: usize = 256;
 pub const SCREEN_HEIGHT: usize = 240;
@@ -264,13 +262,13 @@ save_enum!(PpuAddrByte { Hi, Lo });
 // PPU VRAM. This implements the same Mem trait that the CPU memory does.
 
 pub struct Vram {
-    pub mapper: Rc<RefCell<Box<dyn Mapper + Send>>>,
+    pub mapper: Box<dyn Mapper + Send>,
     pub nametables: [u8; 0x800], // 2 nametables, 0x400 each. FIXME: Not correct for all mappers.
     pub palette: [u8; 0x20],
 }
 
 impl Vram {
-    pub fn new(mapper: Rc<RefCell<Box<dyn Mapper + Send>>>) -> Vram {
+    pub fn new(mapper: Box<dyn Mapper + Send>) -> Vram {
         Vram {
             mapper: mapper,
             nametables: [0; 0x800],
@@ -284,8 +282,7 @@ impl Mem for Vram {
     fn loadb(&mut self, addr: u16) -> u8 {
         if addr < 0x2000 {
             // Tilesets 0 or 1
-            let mut mapper = self.mapper.borrow_mut();
-            mapper.chr_loadb(addr)
+            self.mapper.chr_loadb(addr)
         } else if addr < 0x3f00 {
             // Name table area
             self.nametables[addr as usize & 0x07ff]
@@ -298,8 +295,7 @@ impl Mem for Vram {
     }
     fn storeb(&mut self, addr: u16, val: u8) {
         if addr < 0x2000 {
-            let mut mapper = self.mapper.borrow_mut();
-            mapper.chr_storeb(addr, val)
+            self.mapper.chr_storeb(addr, val)
         } else if addr < 0x3f00 {
             // Name table area
             let addr = addr & 0x07ff;
@@ -316,17 +312,19 @@ impl Mem for Vram {
 }
 
 impl Save for Vram {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         let mut nametables: &mut [u8] = &mut self.nametables;
         nametables.save(fd);
         let mut palette: &mut [u8] = &mut self.palette;
         palette.save(fd);
+        self.mapper.save(fd);
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         let mut nametables: &mut [u8] = &mut self.nametables;
         nametables.load(fd);
         let mut palette: &mut [u8] = &mut self.palette;
         palette.load(fd);
+        self.mapper.load(fd);
     }
 }
 
@@ -354,11 +352,11 @@ impl Mem for Oam {
 }
 
 impl Save for Oam {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         let mut oam: &mut [u8] = &mut self.oam;
         oam.save(fd);
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         let mut oam: &mut [u8] = &mut self.oam;
         oam.load(fd);
     }
@@ -522,7 +520,7 @@ use self::SpritePriority::*;
 use std::num::Wrapping;
 
 impl Save for Ppu {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         self.regs.save(fd);
         self.vram.save(fd);
         self.oam.save(fd);
@@ -532,7 +530,7 @@ impl Save for Ppu {
         self.scroll_y.save(fd);
         self.cy.save(fd);
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         self.regs.load(fd);
         self.vram.load(fd);
         self.oam.load(fd);
@@ -545,6 +543,11 @@ impl Save for Ppu {
 }
 
 impl Ppu {
+    // The cartridge's mapper, also used by the CPU for PRG accesses
+    pub fn mapper(&mut self) -> &mut (dyn Mapper + Send) {
+        &mut *self.vram.mapper
+    }
+
     pub fn new(vram: Vram, oam: Oam) -> Ppu {
         Ppu {
             regs: Regs {
@@ -959,11 +962,8 @@ impl Ppu {
 
             self.scanline += 1;
 
-            {
-                let mut mapper = self.vram.mapper.borrow_mut();
-                if mapper.next_scanline() == MapperResult::Irq {
-                    result.scanline_irq = true
-                }
+            if self.vram.mapper.next_scanline() == MapperResult::Irq {
+                result.scanline_irq = true
             }
 
             if self.scanline == (VBLANK_SCANLINE as u16) {
@@ -25,6 +25,7 @@ impl From<io::Error> for RomLoadError {
 }
 
 /// A ROM image
+#[derive(Clone)]
 pub struct Rom {
     pub header: INesHeader,
     /// PRG-ROM
@@ -70,6 +71,7 @@ impl Rom {
     }
 }
 
+#[derive(Clone)]
 pub struct INesHeader {
     /// 'N' 'E' 'S' '\x1a'
     pub magic: [u8; 4],
@@ -2,7 +2,6 @@
 // Author: Patrick Walton
 //
 
-use std::fs::File;
 use std::io::{self, Read, Write};
 
 /// Reads until the buffer is filled or the reader signals EOF
@@ -33,15 +32,15 @@ pub fn read_to_buf(buf: &mut [u8], rd: &mut dyn Read) -> io::Result<()> {
 // TODO: use `serde` (if it's ready) or `rustc-serialize` and `bincode`
 
 pub trait Save {
-    fn save(&mut self, fd: &mut File);
-    fn load(&mut self, fd: &mut File);
+    fn save(&mut self, fd: &mut dyn Write);
+    fn load(&mut self, fd: &mut dyn Read);
 }
 
 impl Save for u8 {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         fd.write_all(&[*self]).unwrap();
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         let mut buf = [0];
         read_to_buf(&mut buf, fd).unwrap();
         *self = buf[0];
@@ -49,10 +48,10 @@ impl Save for u8 {
 }
 
 impl Save for u16 {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         fd.write_all(&[*self as u8, (*self >> 8) as u8]).unwrap();
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         let mut buf = [0, 0];
         read_to_buf(&mut buf, fd).unwrap();
         *self = (buf[0] as u16) | ((buf[1] as u16) << 8);
@@ -60,14 +59,14 @@ impl Save for u16 {
 }
 
 impl Save for u64 {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         let mut buf = [0; 8];
         for i in 0..8 {
             buf[i] = ((*self) >> (i * 8)) as u8;
         }
         fd.write_all(&buf).unwrap();
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         let mut buf = [0; 8];
         read_to_buf(&mut buf, fd).unwrap();
         *self = 0;
@@ -78,33 +77,66 @@ impl Save for u64 {
 }
 
 impl<'a> Save for &'a mut [u8] {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         fd.write(*self).unwrap();
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         read_to_buf(self, fd).unwrap();
     }
 }
 
 impl Save for bool {
-    fn save(&mut self, fd: &mut File) {
+    fn save(&mut self, fd: &mut dyn Write) {
         fd.write(&[if *self { 0 } else { 1 }]).unwrap();
     }
-    fn load(&mut self, fd: &mut File) {
+    fn load(&mut self, fd: &mut dyn Read) {
         let mut val: [u8; 1] = [0];
         read_to_buf(&mut val, fd).unwrap();
         *self = val[0] != 0
     }
 }
 
+/// An in-memory save state. Restoring one costs no disk I/O, and save states can be sent to
+/// other threads.
+#[derive(Clone)]
+pub struct SaveState {
+    data: Vec<u8>,
+}
+
+impl SaveState {
+    /// Takes a snapshot of the given value
+    pub fn take<T: Save>(value: &mut T) -> SaveState {
+        let mut data = Vec::new();
+        value.save(&mut data);
+        SaveState { data }
+    }
+
+    /// Reads a save state previously written to disk
+    pub fn read(rd: &mut dyn Read) -> io::Result<SaveState> {
+        let mut data = Vec::new();
+        rd.read_to_end(&mut data)?;
+        Ok(SaveState { data })
+    }
+
+    /// Writes the save state so that it can be read back with `SaveState::read`
+    pub fn write(&self, wr: &mut dyn Write) -> io::Result<()> {
+        wr.write_all(&self.data)
+    }
+
+    /// Restores the snapshot into the given value
+    pub fn restore<T: Save>(&self, value: &mut T) {
+        value.load(&mut &self.data[..]);
+    }
+}
+
 // A convenience macro to save and load entire structs.
 macro_rules! save_struct(
     ($name:ident { $($field:ident),* }) => (
         impl Save for $name {
-            fn save(&mut self, fd: &mut File) {
+            fn save(&mut self, fd: &mut dyn (::std::io::Write)) {
                 $(self.$field.save(fd);)*
             }
-            fn load(&mut self, fd: &mut File) {
+            fn load(&mut self, fd: &mut dyn (::std::io::Read)) {
                 $(self.$field.load(fd);)*
             }
         }
@@ -114,11 +146,11 @@ macro_rules! save_struct(
 macro_rules! save_enum(
     ($name:ident { $val_0:ident, $val_1:ident }) => (
         impl Save for $name {
-            fn save(&mut self, fd: &mut File) {
+            fn save(&mut self, fd: &mut dyn (::std::io::Write)) {
                 let mut val: u8 = match *self { $name::$val_0 => 0, $name::$val_1 => 1 };
                 val.save(fd)
             }
-            fn load(&mut self, fd: &mut File) {
+            fn load(&mut self, fd: &mut dyn (::std::io::Read)) {
                 let mut val: u8 = 0;
                 val.load(fd);
                 *self = if val == 0 { $name::$val_0 } else { $name::$val_1 };
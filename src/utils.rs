@@ -1,9 +1,25 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Copy, Clone)]
 pub enum Tile {
     Nothing = 0,
-    Block = 1,
+    Solid = 1,
     Enemy = 2,
     Mario = 3,
+    // Enemies that can't be stomped
+    Hazard = 4,
+    Projectile = 5,
+    Boss = 6,
+    // Bricks, which big Mario can break
+    Breakable = 7,
+    // Question blocks holding a coin
+    Question = 8,
+    Pipe = 9,
+    Coin = 10,
+    // Question blocks holding a power-up, and power-ups that came out of one
+    PowerUp = 11,
+    // No floor at the bottom of the level, where Mario would fall
+    Pit = 12,
 }
 
 impl Default for Tile {
@@ -12,10 +28,48 @@ impl Default for Tile {
     }
 }
 
-// How many blocks NN sees to left, right, top and bottom of Mario
-pub const VIEW_SIZE: i32 = 6;
+// How many blocks NN sees ahead of (to the right of), behind, above and below
+// Mario
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct View {
+    pub ahead: usize,
+    pub behind: usize,
+    pub above: usize,
+    pub below: usize,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            ahead: 6,
+            behind: 6,
+            above: 6,
+            below: 6,
+        }
+    }
+}
+
+impl View {
+    pub fn width(&self) -> usize {
+        self.behind + 1 + self.ahead
+    }
+
+    pub fn height(&self) -> usize {
+        self.above + 1 + self.below
+    }
+
+    // Number of tiles in the view, and so of tile input nodes
+    pub fn tiles(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    pub fn empty_screen(&self) -> Screen {
+        vec![vec![Tile::Nothing; self.width()]; self.height()]
+    }
+}
 
-pub const SCREEN_SIZE: usize = (VIEW_SIZE as usize * 2) + 1;
 // Each block in SMB is a 'megatile'. Each megatile consists of four 8x8 tiles.
-// The NN sees the screen as a 13x13 square of megatiles.
-pub type Screen = [[Tile; SCREEN_SIZE]; SCREEN_SIZE];
+// The NN sees the screen as rows of megatiles, `View::height` rows of
+// `View::width` megatiles each.
+pub type Screen = Vec<Vec<Tile>>;